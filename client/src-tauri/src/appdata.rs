@@ -27,6 +27,13 @@ pub fn read_appdata(appdata: PathBuf) -> Result<Mutex<Vec<AppUser>>, PassyError>
 
     let users = users_dirs
         .iter()
+        .filter(|dir| {
+            // Hidden entries are staging and backup directories of vaults, vaults are
+            // directories, anything else is not a user
+            dir.as_ref()
+                .map(|d| !d.file_name().to_string_lossy().starts_with('.') && d.path().is_dir())
+                .unwrap_or(true)
+        })
         .map(|dir| {
             dir.as_ref().and_then(|d| {
                 let name = d.file_name().to_string_lossy().to_string();
@@ -0,0 +1,128 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{error::PassyError, names::random_name};
+
+/// Suffix of the files content is written to before being moved to its final path
+const TEMP_SUFFIX: &str = ".passy-tmp";
+
+/// Writes `content` to `path`, which holds either its old content or the new one whatever
+/// happens during the write.
+///
+/// The content goes to a temporary file of the same directory, which is synced to disk and then
+/// renamed over `path`.
+pub fn write(path: &Path, content: impl AsRef<[u8]>) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .ok_or(io::Error::from(io::ErrorKind::InvalidInput))?;
+    let temp = dir.join(format!(
+        ".{}.{}{}",
+        name.to_string_lossy(),
+        random_name(),
+        TEMP_SUFFIX
+    ));
+
+    let res = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        file.write_all(content.as_ref())?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+
+    if res.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+    res?;
+
+    sync_dir(dir)
+}
+
+/// Makes the entries of `dir`, such as a renamed file, durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Removes the temporary files left behind by writes that were interrupted, e.g. by a crash
+pub fn remove_temp_files(path: &Path) -> Result<(), PassyError> {
+    let mut result = Ok(());
+
+    // A file that can't be removed must not keep the others from being removed
+    for res in fs::read_dir(path).map_err(|_| PassyError::FailedToReadDir(path.to_owned()))? {
+        let entry = match res {
+            Ok(entry) => entry,
+            Err(_) => {
+                result = Err(PassyError::FailedToReadDir(path.to_owned()));
+                continue;
+            }
+        };
+
+        if entry.path().is_dir() {
+            // Nothing is ever written there with a temporary file
+            if entry.file_name() != ".git" {
+                if let Err(e) = remove_temp_files(&entry.path()) {
+                    result = Err(e);
+                }
+            }
+        } else if entry.file_name().to_string_lossy().ends_with(TEMP_SUFFIX)
+            && fs::remove_file(entry.path()).is_err()
+        {
+            result = Err(PassyError::DeletationFailed(entry.path()));
+        }
+    }
+
+    result
+}
+
+/// Removes the temporary files left behind by interrupted writes to `path` itself
+pub fn remove_temp_files_of(path: &Path) -> Result<(), PassyError> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let prefix = match path.file_name() {
+        Some(name) => format!(".{}.", name.to_string_lossy()),
+        None => return Ok(()),
+    };
+
+    let mut result = Ok(());
+
+    for res in fs::read_dir(dir).map_err(|_| PassyError::FailedToReadDir(dir.to_owned()))? {
+        let entry = match res {
+            Ok(entry) => entry,
+            Err(_) => {
+                result = Err(PassyError::FailedToReadDir(dir.to_owned()));
+                continue;
+            }
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // Random names have no dots, which tells them apart from temporary files of other paths
+        // starting with the same name
+        let is_temp = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(TEMP_SUFFIX))
+            .is_some_and(|random| !random.contains('.'));
+
+        if is_temp && fs::remove_file(entry.path()).is_err() {
+            result = Err(PassyError::DeletationFailed(entry.path()));
+        }
+    }
+
+    result
+}
@@ -0,0 +1,142 @@
+use std::{
+    cmp::Ordering,
+    fs::{self, File},
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::Path,
+};
+
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::{error::PassyError, password::recursive_pwd_read, vault::Vault};
+
+/// Length of the hash prefixes naming the files of a prefix-split list
+const PREFIX_LEN: usize = 5;
+
+/// A password of the vault found in the breached passwords list
+#[derive(Serialize)]
+pub struct Breach {
+    pub path: String,
+    /// Number of times the password appears in the breaches
+    pub count: u64,
+}
+
+/// Checks every password of the vault against a local Have I Been Pwned hash list, nothing is
+/// sent over the network.
+///
+/// `hash_list` is either a single file of `HASH:COUNT` lines sorted by SHA-1 hash, or a directory
+/// of `PREFIX.txt` files holding `SUFFIX:COUNT` lines, as given by the k-anonymity range API.
+pub fn check_vault(vault: &Vault, hash_list: &Path) -> Result<Vec<Breach>, PassyError> {
+    let mut breaches = Vec::new();
+
+    for password in recursive_pwd_read(vault)? {
+        let value = match password.password() {
+            Some(value) if !value.is_empty() => value,
+            _ => continue,
+        };
+
+        let hash: String = Sha1::digest(value.as_bytes())
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+
+        let count = if hash_list.is_dir() {
+            search_prefix_file(hash_list, &hash)
+        } else {
+            search_sorted_file(hash_list, &hash)
+        }
+        .map_err(|_| PassyError::FailedToReadHashList(hash_list.to_owned()))?;
+
+        if let Some(count) = count {
+            breaches.push(Breach {
+                path: password.path,
+                count,
+            });
+        }
+    }
+
+    Ok(breaches)
+}
+
+/// Compares the hash at the start of a `HASH:COUNT` line with `hash`, ignoring case
+fn compare_line(line: &str, hash: &str) -> Ordering {
+    let line_hash = line.split(':').next().unwrap_or("").trim();
+    line_hash
+        .bytes()
+        .map(|b| b.to_ascii_uppercase())
+        .cmp(hash.bytes())
+}
+
+fn parse_count(line: &str) -> u64 {
+    line.split(':')
+        .nth(1)
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(1)
+}
+
+/// Binary searches `hash` in a whole sorted list, without loading it in memory
+fn search_sorted_file(path: &Path, hash: &str) -> std::io::Result<Option<u64>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let (mut low, mut high) = (0, reader.get_ref().metadata()?.len());
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+
+        let (start, line) = match line_from(&mut reader, middle)? {
+            Some((start, line)) if start < high => (start, line),
+            // No line starts between `middle` and `high`
+            _ => {
+                high = middle;
+                continue;
+            }
+        };
+
+        match compare_line(&line, hash) {
+            Ordering::Equal => return Ok(Some(parse_count(&line))),
+            Ordering::Less => low = start + line.len() as u64,
+            // No line starts between `middle` and `start`, so the searched one is before `middle`
+            Ordering::Greater => high = middle,
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads the first line starting at or after `offset`, along with its start, line break included
+fn line_from(reader: &mut BufReader<File>, offset: u64) -> std::io::Result<Option<(u64, String)>> {
+    let start = if offset == 0 {
+        reader.seek(SeekFrom::Start(0))?
+    } else {
+        // Skips the end of the line `offset` is in, unless it already is a line start
+        reader.seek(SeekFrom::Start(offset - 1))?;
+        offset - 1 + reader.read_until(b'\n', &mut Vec::new())? as u64
+    };
+
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some((start, line)))
+}
+
+/// Searches `hash` in the file of its prefix, in a prefix-split list
+fn search_prefix_file(dir: &Path, hash: &str) -> std::io::Result<Option<u64>> {
+    let (prefix, suffix) = hash.split_at(PREFIX_LEN);
+
+    let file = [format!("{}.txt", prefix), prefix.to_string()]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|file| file.exists());
+
+    let content = match file {
+        Some(file) => fs::read_to_string(file)?,
+        None => return Ok(None),
+    };
+
+    let lines: Vec<&str> = content.lines().filter(|l| !l.trim().is_empty()).collect();
+    Ok(lines
+        .binary_search_by(|line| compare_line(line, suffix))
+        .ok()
+        .map(|i| parse_count(lines[i])))
+}
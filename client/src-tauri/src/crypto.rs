@@ -1,25 +1,105 @@
 use aes_gcm::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
-    Aes256Gcm, Key,
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm,
 };
+use chacha20poly1305::XChaCha20Poly1305;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
 
-pub fn cipher(key: &[u8; 32], data: &[u8]) -> Result<(Vec<u8>, [u8; 12]), aes_gcm::Error> {
-    let aes_key: &Key<Aes256Gcm> = key.into();
+use crate::secret::{SecretBytes, SecretKey};
 
-    let cipher = Aes256Gcm::new(aes_key);
-    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
-    cipher
-        .encrypt(&nonce, data)
-        .and_then(|res| Ok((res, nonce.into())))
+/// Authenticated cipher used for the passwords of a vault
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum CipherSuite {
+    /// AES-256-GCM with random 96 bits nonces
+    #[default]
+    Aes256Gcm = 1,
+    /// XChaCha20-Poly1305 with random 192 bits nonces
+    XChaCha20Poly1305 = 2,
 }
 
-pub fn decipher(
-    key: &[u8; 32],
-    nonce: [u8; 12],
-    encrypted_data: &[u8],
-) -> Result<Vec<u8>, aes_gcm::Error> {
-    let aes_key: &Key<Aes256Gcm> = key.into();
+impl CipherSuite {
+    pub fn nonce_len(self) -> usize {
+        match self {
+            CipherSuite::Aes256Gcm => 12,
+            CipherSuite::XChaCha20Poly1305 => 24,
+        }
+    }
 
-    let cipher = Aes256Gcm::new(aes_key);
-    cipher.decrypt(&nonce.into(), encrypted_data)
+    /// Ciphers `data`, `aad` is authenticated but not ciphered and must be given again to decipher.
+    ///
+    /// Returns the ciphered data and the random nonce used.
+    pub fn cipher(
+        self,
+        key: &[u8; 32],
+        data: &[u8],
+        aad: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), aes_gcm::Error> {
+        let payload = Payload { msg: data, aad };
+
+        match self {
+            CipherSuite::Aes256Gcm => {
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+                Aes256Gcm::new(key.into())
+                    .encrypt(&nonce, payload)
+                    .map(|res| (res, nonce.to_vec()))
+            }
+            CipherSuite::XChaCha20Poly1305 => {
+                let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+                XChaCha20Poly1305::new(key.into())
+                    .encrypt(&nonce, payload)
+                    .map(|res| (res, nonce.to_vec()))
+            }
+        }
+    }
+
+    pub fn decipher(
+        self,
+        key: &[u8; 32],
+        nonce: &[u8],
+        encrypted_data: &[u8],
+        aad: &[u8],
+    ) -> Result<SecretBytes, aes_gcm::Error> {
+        if nonce.len() != self.nonce_len() {
+            return Err(aes_gcm::Error);
+        }
+
+        let payload = Payload {
+            msg: encrypted_data,
+            aad,
+        };
+
+        match self {
+            CipherSuite::Aes256Gcm => Aes256Gcm::new(key.into()).decrypt(nonce.into(), payload),
+            CipherSuite::XChaCha20Poly1305 => {
+                XChaCha20Poly1305::new(key.into()).decrypt(nonce.into(), payload)
+            }
+        }
+        .map(Zeroizing::new)
+    }
+}
+
+/// Ciphers `data` with AES-256-GCM into a single buffer: the nonce followed by the ciphered data
+pub fn seal(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, aes_gcm::Error> {
+    let (mut content, mut sealed) = CipherSuite::Aes256Gcm.cipher(key, data, &[])?;
+
+    sealed.append(&mut content);
+    Ok(sealed)
+}
+
+/// Deciphers data produced by [`seal`]
+pub fn open(key: &[u8; 32], sealed: &[u8]) -> Result<SecretBytes, aes_gcm::Error> {
+    let nonce_len = CipherSuite::Aes256Gcm.nonce_len();
+    if sealed.len() < nonce_len {
+        return Err(aes_gcm::Error);
+    }
+
+    let (nonce, content) = sealed.split_at(nonce_len);
+    CipherSuite::Aes256Gcm.decipher(key, nonce, content, &[])
+}
+
+pub fn generate_key() -> SecretKey {
+    let mut key = SecretKey::new([0; 32]);
+    OsRng.fill_bytes(key.as_mut());
+    key
 }
@@ -7,6 +7,10 @@ pub enum PasswordReadFailedReason {
     FileNotFound,
     DecipherError,
     MalformedMetadata,
+    UnsupportedVersion(u8),
+    UnknownCipher(u8),
+    UnknownKdf(u8),
+    PathMismatch,
 }
 
 #[derive(Debug, Clone)]
@@ -63,8 +67,39 @@ pub enum PassyError {
     FailedToReadPassword(PathBuf, PasswordReadFailedReason),
     FailedToWritePassword(PathBuf, PasswordWriteFailedReason),
     DeletationFailed(PathBuf),
+    PasswordAlreadyExists(String),
     PluginError(String, PluginErrorKind),
     NotLoggedIn,
+    KeyDerivationFailed,
+    WrongMasterPassword,
+    KeyFileRequired,
+    WrongKeyFile,
+    FailedToReadKeyFile(PathBuf),
+    FailedToWriteKeyFile(PathBuf),
+    FailedToReadVaultConfig(PathBuf),
+    FailedToWriteVaultConfig(PathBuf),
+    FailedToCopyFile(PathBuf),
+    VaultSwapFailed(Username),
+    FailedToReadIndex(PathBuf),
+    FailedToWriteIndex(PathBuf),
+    InvalidGeneratorOptions(String),
+    FailedToReadHashList(PathBuf),
+    InvalidOtp(String),
+    NoOtp(String),
+    InvalidOtpMigration(String),
+    FailedToReadQrImage(PathBuf),
+    GitError(String),
+    GitHistoryDisabled(Username),
+    NotInVersion(String, String),
+    NoSyncRemote(Username),
+    SyncPullNeeded,
+    NotInTrash(String),
+    FailedToReadHistory(PathBuf),
+    FailedToWriteHistory(PathBuf),
+    RevisionNotFound(String, u64),
+    VaultLocked(Username, Option<u32>),
+    FailedToLockVault(Username),
+    FailedToWatchVault(PathBuf),
     UnknowError(&'static dyn Error),
 }
 
@@ -88,9 +123,18 @@ impl From<PassyError> for String {
                     path.to_string_lossy().to_string(),
                     match reason {
                         PasswordReadFailedReason::DecipherError =>
-                            "failed to decipher password data",
-                        PasswordReadFailedReason::FileNotFound => "file not found",
-                        PasswordReadFailedReason::MalformedMetadata => "malformed metadata in file",
+                            "failed to decipher password data".to_string(),
+                        PasswordReadFailedReason::FileNotFound => "file not found".to_string(),
+                        PasswordReadFailedReason::MalformedMetadata =>
+                            "malformed metadata in file".to_string(),
+                        PasswordReadFailedReason::UnsupportedVersion(version) =>
+                            format!("unsupported file format version {}", version),
+                        PasswordReadFailedReason::UnknownCipher(id) =>
+                            format!("unknown cipher id {}", id),
+                        PasswordReadFailedReason::UnknownKdf(id) =>
+                            format!("unknown kdf id {}", id),
+                        PasswordReadFailedReason::PathMismatch =>
+                            "file was moved outside of Passy or tampered with".to_string(),
                     }
                 )
             }
@@ -107,13 +151,92 @@ impl From<PassyError> for String {
                 "Failed to delete password at {}",
                 path.to_string_lossy().to_string()
             ),
+            PassyError::PasswordAlreadyExists(path) => {
+                format!("A password already exists at {}", path)
+            }
             PassyError::PluginError(name, kind) => {
                 format!("Error happened in plugin {}, {}", name, kind.to_string())
             }
             PassyError::NotLoggedIn => "Not logged in".to_string(),
+            PassyError::KeyDerivationFailed => {
+                "Failed to derive key from master password".to_string()
+            }
+            PassyError::WrongMasterPassword => "Wrong master password".to_string(),
+            PassyError::KeyFileRequired => "This vault requires a key file".to_string(),
+            PassyError::WrongKeyFile => "Wrong key file".to_string(),
+            PassyError::FailedToReadKeyFile(path) => {
+                format!("Failed to read key file at {}", path.to_string_lossy())
+            }
+            PassyError::FailedToWriteKeyFile(path) => {
+                format!("Failed to write key file at {}", path.to_string_lossy())
+            }
+            PassyError::FailedToReadVaultConfig(path) => {
+                format!("Failed to read vault config at {}", path.to_string_lossy())
+            }
+            PassyError::FailedToWriteVaultConfig(path) => {
+                format!("Failed to write vault config at {}", path.to_string_lossy())
+            }
             PassyError::NoAppdataDir => "Can't find appdata dir".to_string(),
             PassyError::UserNotFound(user) => format!("User {} not found", user),
             PassyError::UserAlreadyExists(user) => format!("User {} already exists", user),
+            PassyError::FailedToCopyFile(path) => {
+                format!("Failed to copy file at {}", path.to_string_lossy())
+            }
+            PassyError::VaultSwapFailed(user) => {
+                format!("Failed to replace vault of user {}", user)
+            }
+            PassyError::FailedToReadIndex(path) => {
+                format!("Failed to read names index at {}", path.to_string_lossy())
+            }
+            PassyError::FailedToWriteIndex(path) => {
+                format!("Failed to write names index at {}", path.to_string_lossy())
+            }
+            PassyError::InvalidGeneratorOptions(reason) => {
+                format!("Can't generate password, {}", reason)
+            }
+            PassyError::FailedToReadHashList(path) => {
+                format!("Failed to read hash list at {}", path.to_string_lossy())
+            }
+            PassyError::InvalidOtp(reason) => format!("Invalid one-time code settings, {}", reason),
+            PassyError::NoOtp(path) => format!("Password {} has no one-time code settings", path),
+            PassyError::InvalidOtpMigration(reason) => {
+                format!("Invalid authenticator export, {}", reason)
+            }
+            PassyError::FailedToReadQrImage(path) => {
+                format!("Failed to read QR code image at {}", path.to_string_lossy())
+            }
+            PassyError::GitError(message) => format!("Git error, {}", message),
+            PassyError::GitHistoryDisabled(user) => {
+                format!("History is not enabled for user {}", user)
+            }
+            PassyError::NotInVersion(path, id) => {
+                format!("Password {} doesn't exist in version {}", path, id)
+            }
+            PassyError::NoSyncRemote(user) => format!("No sync remote is set for user {}", user),
+            PassyError::SyncPullNeeded => {
+                "The remote has changes that need to be pulled first".to_string()
+            }
+            PassyError::NotInTrash(id) => format!("No password with id {} in the trash", id),
+            PassyError::FailedToReadHistory(path) => {
+                format!("Failed to read history at {}", path.to_string_lossy())
+            }
+            PassyError::FailedToWriteHistory(path) => {
+                format!("Failed to write history at {}", path.to_string_lossy())
+            }
+            PassyError::RevisionNotFound(path, id) => {
+                format!("Password {} has no revision {}", path, id)
+            }
+            PassyError::VaultLocked(user, pid) => match pid {
+                Some(pid) => format!(
+                    "Vault of user {} is being used by another process (PID {})",
+                    user, pid
+                ),
+                None => format!("Vault of user {} is being used by another process", user),
+            },
+            PassyError::FailedToLockVault(user) => format!("Failed to lock vault of user {}", user),
+            PassyError::FailedToWatchVault(path) => {
+                format!("Failed to watch changes of {}", path.to_string_lossy())
+            }
             PassyError::UnknowError(e) => e.to_string(),
         }
     }
@@ -0,0 +1,242 @@
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use serde::Deserialize;
+
+use crate::{error::PassyError, secret::SecretString};
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!#$%&*+-=?@^_~()[]{}<>.,:;/|";
+/// Characters easily mistaken for one another, like `l`, `1` and `I`
+const AMBIGUOUS: &str = "il1Lo0O|";
+
+const CONSONANTS: &str = "bcdfghjklmnpqrstvwxz";
+const VOWELS: &str = "aeiouy";
+
+/// Longest password that can be generated, far beyond what any site accepts
+const MAX_LENGTH: usize = 1024;
+/// Most words a passphrase can have
+const MAX_WORDS: usize = 256;
+/// Longest separator between the words of a passphrase
+const MAX_SEPARATOR_LENGTH: usize = 16;
+
+/// BIP39 english wordlist, 2048 words so each one adds 11 bits of entropy
+pub const WORDLIST: &str = include_str!("wordlist.txt");
+
+/// Settings of the generated password, chosen with the `kind` field
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GeneratorOptions {
+    /// Random characters from the chosen classes, at least one of each
+    Random {
+        #[serde(default = "default_length")]
+        length: usize,
+        #[serde(default = "enabled")]
+        lowercase: bool,
+        #[serde(default = "enabled")]
+        uppercase: bool,
+        #[serde(default = "enabled")]
+        digits: bool,
+        #[serde(default = "enabled")]
+        symbols: bool,
+        #[serde(default = "enabled")]
+        exclude_ambiguous: bool,
+    },
+    /// Alternating consonants and vowels, easier to type and remember
+    Pronounceable {
+        #[serde(default = "default_length")]
+        length: usize,
+    },
+    /// Diceware style passphrase of random words
+    Passphrase {
+        #[serde(default = "default_words")]
+        words: usize,
+        #[serde(default = "default_separator")]
+        separator: String,
+    },
+}
+
+fn default_length() -> usize {
+    20
+}
+
+fn default_words() -> usize {
+    6
+}
+
+fn default_separator() -> String {
+    "-".to_string()
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions::Random {
+            length: default_length(),
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            exclude_ambiguous: true,
+        }
+    }
+}
+
+impl GeneratorOptions {
+    pub fn generate(&self) -> Result<SecretString, PassyError> {
+        match self {
+            GeneratorOptions::Random {
+                length,
+                lowercase,
+                uppercase,
+                digits,
+                symbols,
+                exclude_ambiguous,
+            } => {
+                let classes: Vec<Vec<char>> = [
+                    (*lowercase, LOWERCASE),
+                    (*uppercase, UPPERCASE),
+                    (*digits, DIGITS),
+                    (*symbols, SYMBOLS),
+                ]
+                .iter()
+                .filter(|(enabled, _)| *enabled)
+                .map(|(_, class)| {
+                    class
+                        .chars()
+                        .filter(|c| !*exclude_ambiguous || !AMBIGUOUS.contains(*c))
+                        .collect()
+                })
+                .collect();
+
+                if classes.is_empty() {
+                    return Err(PassyError::InvalidGeneratorOptions(
+                        "no character class selected".to_string(),
+                    ));
+                }
+                check_length(*length)?;
+                if *length < classes.len() {
+                    return Err(PassyError::InvalidGeneratorOptions(format!(
+                        "length must be at least {} to use every selected class",
+                        classes.len()
+                    )));
+                }
+
+                let all: Vec<char> = classes.concat();
+                let mut chars = Vec::with_capacity(*length);
+                chars.extend(classes.iter().map(|class| pick(class)));
+                while chars.len() < *length {
+                    chars.push(pick(&all));
+                }
+                shuffle(&mut chars);
+
+                Ok(collect_secret(chars))
+            }
+            GeneratorOptions::Pronounceable { length } => {
+                if *length == 0 {
+                    return Err(PassyError::InvalidGeneratorOptions(
+                        "length must be at least 1".to_string(),
+                    ));
+                }
+                check_length(*length)?;
+
+                let consonants: Vec<char> = CONSONANTS.chars().collect();
+                let vowels: Vec<char> = VOWELS.chars().collect();
+                let vowel_first = random_index(2) == 0;
+
+                let chars = (0..*length)
+                    .map(|i| {
+                        if (i % 2 == 0) == vowel_first {
+                            pick(&vowels)
+                        } else {
+                            pick(&consonants)
+                        }
+                    })
+                    .collect();
+
+                Ok(collect_secret(chars))
+            }
+            GeneratorOptions::Passphrase { words, separator } => {
+                if *words == 0 {
+                    return Err(PassyError::InvalidGeneratorOptions(
+                        "a passphrase needs at least 1 word".to_string(),
+                    ));
+                }
+                if *words > MAX_WORDS {
+                    return Err(PassyError::InvalidGeneratorOptions(format!(
+                        "a passphrase can't have more than {} words",
+                        MAX_WORDS
+                    )));
+                }
+                if separator.len() > MAX_SEPARATOR_LENGTH {
+                    return Err(PassyError::InvalidGeneratorOptions(format!(
+                        "the separator can't be longer than {} bytes",
+                        MAX_SEPARATOR_LENGTH
+                    )));
+                }
+
+                let wordlist: Vec<&str> = WORDLIST.lines().collect();
+                let max_word_len = wordlist.iter().map(|w| w.len()).max().unwrap_or(0);
+
+                // Sized upfront so that no partial copy is left behind by reallocations
+                let mut passphrase =
+                    String::with_capacity((max_word_len + separator.len()) * words);
+                for i in 0..*words {
+                    if i > 0 {
+                        passphrase.push_str(separator);
+                    }
+                    passphrase.push_str(pick(&wordlist));
+                }
+
+                Ok(SecretString::from(passphrase))
+            }
+        }
+    }
+}
+
+fn check_length(length: usize) -> Result<(), PassyError> {
+    if length > MAX_LENGTH {
+        return Err(PassyError::InvalidGeneratorOptions(format!(
+            "length can't be more than {}",
+            MAX_LENGTH
+        )));
+    }
+
+    Ok(())
+}
+
+/// Uniformly random index below `bound`, from the OS CSPRNG
+fn random_index(bound: usize) -> usize {
+    let bound = bound as u64;
+    // Values above the last multiple of `bound` are rejected so that every index is equally likely
+    let zone = u64::MAX - u64::MAX % bound;
+
+    loop {
+        let value = OsRng.next_u64();
+        if value < zone {
+            return (value % bound) as usize;
+        }
+    }
+}
+
+fn pick<T: Copy>(items: &[T]) -> T {
+    items[random_index(items.len())]
+}
+
+/// Fisher-Yates shuffle
+fn shuffle<T>(items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, random_index(i + 1));
+    }
+}
+
+/// Turns `chars` into a secret string, wiping them along the way
+fn collect_secret(mut chars: Vec<char>) -> SecretString {
+    let mut secret = String::with_capacity(chars.iter().map(|c| c.len_utf8()).sum());
+    secret.extend(chars.iter());
+    chars.iter_mut().for_each(|c| *c = '\0');
+    SecretString::from(secret)
+}
@@ -0,0 +1,266 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use git2::{Commit, IndexAddOption, Oid, Repository, Signature, Sort, Tree};
+use serde::Serialize;
+
+use crate::{
+    error::PassyError,
+    names::{NamesIndex, NAMES_INDEX_FILE},
+    password::Password,
+    revisions,
+    vault::{Vault, LOCAL_CONFIG_FILES},
+};
+
+/// A commit of the vault history
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub message: String,
+    /// Unix time of the commit
+    pub time: i64,
+}
+
+pub fn git_error(e: git2::Error) -> PassyError {
+    PassyError::GitError(e.message().to_string())
+}
+
+pub fn open_repo(vault: &Vault) -> Result<Repository, PassyError> {
+    if !vault.config.git {
+        return Err(PassyError::GitHistoryDisabled(vault.username.clone()));
+    }
+
+    Repository::open(&vault.path).map_err(git_error)
+}
+
+/// Makes the user directory a git repository if it isn't one, and commits its current state
+pub fn init(vault: &Vault) -> Result<(), PassyError> {
+    if Repository::open(&vault.path).is_err() {
+        Repository::init(&vault.path).map_err(git_error)?;
+    }
+
+    commit(vault, "Enable history")
+}
+
+/// Commit message describing `action` on the password at `path`, which is left out for vaults
+/// with hidden names
+pub fn describe(vault: &Vault, action: &str, path: &str) -> String {
+    if vault.config.hidden_names {
+        format!("{} a password", action)
+    } else {
+        format!("{} {}", action, path)
+    }
+}
+
+/// Commits every change of the user directory, does nothing if history is disabled or if
+/// nothing changed
+pub fn commit(vault: &Vault, message: &str) -> Result<(), PassyError> {
+    if !vault.config.git {
+        return Ok(());
+    }
+
+    let repo = open_repo(vault)?;
+    let tree_id = stage_all(&repo)?;
+
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    if parent
+        .as_ref()
+        .is_some_and(|parent| parent.tree_id() == tree_id)
+    {
+        return Ok(());
+    }
+
+    let parents: Vec<&Commit> = parent.iter().collect();
+    commit_tree(&repo, vault, message, tree_id, &parents)
+}
+
+/// Commits every change of the user directory as the merge of the current commit with `theirs`
+pub fn merge_commit(vault: &Vault, message: &str, theirs: Oid) -> Result<(), PassyError> {
+    let repo = open_repo(vault)?;
+    let tree_id = stage_all(&repo)?;
+
+    let ours = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(git_error)?;
+    let theirs = repo.find_commit(theirs).map_err(git_error)?;
+
+    commit_tree(&repo, vault, message, tree_id, &[&ours, &theirs])
+}
+
+/// Stages every change of the user directory, returns the id of the resulting tree
+///
+/// The files of [`LOCAL_CONFIG_FILES`] are left out: the wrapped data key must not stay in the
+/// history once the master password changed, and the sync remote belongs to this device.
+fn stage_all(repo: &Repository) -> Result<Oid, PassyError> {
+    let mut skip_local = |path: &Path, _: &[u8]| -> i32 {
+        let local = LOCAL_CONFIG_FILES
+            .iter()
+            .any(|file| path == Path::new(file));
+        i32::from(local)
+    };
+
+    let mut index = repo.index().map_err(git_error)?;
+    index
+        .add_all(["*"].iter(), IndexAddOption::DEFAULT, Some(&mut skip_local))
+        .map_err(git_error)?;
+    index
+        .update_all(["*"].iter(), Some(&mut skip_local))
+        .map_err(git_error)?;
+    index.write().map_err(git_error)?;
+
+    index.write_tree().map_err(git_error)
+}
+
+fn commit_tree(
+    repo: &Repository,
+    vault: &Vault,
+    message: &str,
+    tree_id: Oid,
+    parents: &[&Commit],
+) -> Result<(), PassyError> {
+    let tree = repo.find_tree(tree_id).map_err(git_error)?;
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now(&vault.username, "passy@localhost"))
+        .map_err(git_error)?;
+
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        parents,
+    )
+    .map_err(git_error)?;
+
+    Ok(())
+}
+
+fn history_entry(commit: &Commit) -> HistoryEntry {
+    HistoryEntry {
+        id: commit.id().to_string(),
+        message: commit.message().unwrap_or_default().trim().to_string(),
+        time: commit.time().seconds(),
+    }
+}
+
+/// Walks the commits of the vault, newest first
+fn walk<'r>(repo: &'r Repository) -> Result<Vec<Commit<'r>>, PassyError> {
+    if repo.head().is_err() {
+        return Ok(Vec::new());
+    }
+
+    let mut revwalk = repo.revwalk().map_err(git_error)?;
+    revwalk
+        .set_sorting(Sort::TIME | Sort::TOPOLOGICAL)
+        .map_err(git_error)?;
+    revwalk.push_head().map_err(git_error)?;
+
+    revwalk
+        .map(|id| id.and_then(|id| repo.find_commit(id)).map_err(git_error))
+        .collect()
+}
+
+pub fn vault_history(vault: &Vault) -> Result<Vec<HistoryEntry>, PassyError> {
+    let repo = open_repo(vault)?;
+    let history = walk(&repo)?.iter().map(history_entry).collect();
+    Ok(history)
+}
+
+/// File of the password at `path` in `tree`, relative to the user directory
+///
+/// Names may have been hidden or not at the time of the commit, so it is told by the tree itself.
+fn file_in_tree(
+    repo: &Repository,
+    vault: &Vault,
+    tree: &Tree,
+    path: &str,
+) -> Result<Option<PathBuf>, PassyError> {
+    let entry = match tree.get_path(Path::new(NAMES_INDEX_FILE)) {
+        Ok(entry) => entry,
+        Err(_) => return Ok(Some(PathBuf::from(path.to_string() + ".passy"))),
+    };
+    let blob = repo.find_blob(entry.id()).map_err(git_error)?;
+    let index = NamesIndex::from_sealed(&vault.key, blob.content(), Path::new(NAMES_INDEX_FILE))?;
+
+    Ok(index
+        .get(path)
+        .map(|file| PathBuf::from(file.to_string() + ".passy")))
+}
+
+/// Id of the blob holding the password at `path` in `tree`
+fn blob_in_tree(
+    repo: &Repository,
+    vault: &Vault,
+    tree: &Tree,
+    path: &str,
+) -> Result<Option<Oid>, PassyError> {
+    Ok(file_in_tree(repo, vault, tree, path)?
+        .and_then(|file| tree.get_path(&file).ok())
+        .map(|entry| entry.id()))
+}
+
+/// Commits that changed the password at `path`, newest first
+pub fn password_history(vault: &Vault, path: &str) -> Result<Vec<HistoryEntry>, PassyError> {
+    let repo = open_repo(vault)?;
+    let commits = walk(&repo)?;
+
+    let mut blobs = HashMap::new();
+    for commit in &commits {
+        let tree = commit.tree().map_err(git_error)?;
+        blobs.insert(commit.id(), blob_in_tree(&repo, vault, &tree, path)?);
+    }
+
+    let mut history = Vec::new();
+    for commit in &commits {
+        let blob = blobs[&commit.id()];
+        let parent_blob = match commit.parent(0) {
+            Ok(parent) => match blobs.get(&parent.id()) {
+                Some(blob) => *blob,
+                None => blob_in_tree(&repo, vault, &parent.tree().map_err(git_error)?, path)?,
+            },
+            Err(_) => None,
+        };
+
+        if blob != parent_blob {
+            history.push(history_entry(commit));
+        }
+    }
+
+    Ok(history)
+}
+
+/// Puts back the password at `path` as it was at commit `id`, and commits it. The current version
+/// is kept in the password history.
+pub fn restore_version(vault: &Vault, path: &str, id: &str) -> Result<Password, PassyError> {
+    let repo = open_repo(vault)?;
+    let version = Oid::from_str(id)
+        .and_then(|id| repo.find_commit(id))
+        .map_err(git_error)?;
+    let tree = version.tree().map_err(git_error)?;
+
+    let blob = blob_in_tree(&repo, vault, &tree, path)?
+        .ok_or(PassyError::NotInVersion(path.to_string(), id.to_string()))?;
+    let blob = repo.find_blob(blob).map_err(git_error)?;
+
+    // Deciphered before anything is written, versions that can't be read with the current key
+    // leave the password as it is
+    let mut password =
+        Password::from_content(vault, Path::new(path), blob.content(), path.to_string())?;
+
+    if let Ok(previous) = Password::read(vault, path) {
+        if previous.data().get() != password.data().get() {
+            revisions::record(vault, previous)?;
+        }
+    }
+
+    password.write(vault)?;
+    password.update_strength();
+    commit(vault, &describe(vault, "Restore", path))?;
+
+    Ok(password)
+}
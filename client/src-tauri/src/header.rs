@@ -0,0 +1,89 @@
+use crate::{crypto::CipherSuite, error::PasswordReadFailedReason};
+
+/// Magic bytes at the start of every `.passy` file written with a header
+pub const MAGIC: &[u8; 4] = b"PASY";
+/// Version 2 authenticates the username and password path as associated data
+pub const CURRENT_VERSION: u8 = 2;
+
+impl TryFrom<u8> for CipherSuite {
+    type Error = PasswordReadFailedReason;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(CipherSuite::Aes256Gcm),
+            2 => Ok(CipherSuite::XChaCha20Poly1305),
+            _ => Err(PasswordReadFailedReason::UnknownCipher(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KdfId {
+    Argon2id = 1,
+}
+
+impl TryFrom<u8> for KdfId {
+    type Error = PasswordReadFailedReason;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(KdfId::Argon2id),
+            _ => Err(PasswordReadFailedReason::UnknownKdf(value)),
+        }
+    }
+}
+
+/// Header of a `.passy` file, followed by the nonce and the ciphered data.
+///
+/// Legacy files have no header and start directly with a 12 bytes AES-256-GCM nonce.
+#[derive(Debug, Clone, Copy)]
+pub struct Header {
+    pub version: u8,
+    pub cipher: CipherSuite,
+    pub kdf: KdfId,
+}
+
+impl Header {
+    pub const LEN: usize = MAGIC.len() + 3;
+
+    pub fn current(cipher: CipherSuite) -> Self {
+        Header {
+            version: CURRENT_VERSION,
+            cipher,
+            kdf: KdfId::Argon2id,
+        }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([self.version, self.cipher as u8, self.kdf as u8]);
+        bytes
+    }
+
+    /// Whether the username and password path are bound to the data as associated data
+    pub fn binds_path(&self) -> bool {
+        self.version >= 2
+    }
+
+    /// Parses the header at the start of `content`, `Ok(None)` means a legacy file
+    pub fn parse(content: &[u8]) -> Result<Option<Self>, PasswordReadFailedReason> {
+        if !content.starts_with(MAGIC) {
+            return Ok(None);
+        }
+
+        let bytes = content
+            .get(MAGIC.len()..Header::LEN)
+            .ok_or(PasswordReadFailedReason::MalformedMetadata)?;
+
+        let version = bytes[0];
+        if version == 0 || version > CURRENT_VERSION {
+            return Err(PasswordReadFailedReason::UnsupportedVersion(version));
+        }
+
+        Ok(Some(Header {
+            version,
+            cipher: CipherSuite::try_from(bytes[1])?,
+            kdf: KdfId::try_from(bytes[2])?,
+        }))
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic,
+    crypto::{open, seal},
+    error::{PasswordReadFailedReason, PassyError},
+    password::{list_entries, Password},
+    secret::SecretString,
+    strength::{self, Strength},
+    vault::Vault,
+};
+
+/// Fields shown in the list of passwords, the others may hold secrets and are only deciphered
+/// when their entry is opened
+const SUMMARY_FIELDS: [&str; 5] = ["username", "email", "url", "name", "issuer"];
+
+/// Changes whenever summaries hold different data, older indexes are rebuilt
+const INDEX_VERSION: u32 = 2;
+
+/// What the list of passwords shows about an entry, without its secrets
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PasswordSummary {
+    pub path: String,
+    /// Fields of the entry that tell what it is for, such as the username or the website
+    pub data: HashMap<String, SecretString>,
+    /// Names of every field of the entry, secrets included
+    pub fields: Vec<String>,
+    pub password_changed: Option<u64>,
+    /// Estimated strength of the password field
+    pub strength: Option<Strength>,
+}
+
+impl From<&Password> for PasswordSummary {
+    fn from(password: &Password) -> Self {
+        let mut fields: Vec<String> = password.data().get().keys().cloned().collect();
+        fields.sort();
+
+        PasswordSummary {
+            path: password.path.clone(),
+            data: password
+                .data()
+                .get()
+                .iter()
+                .filter(|(field, _)| SUMMARY_FIELDS.contains(&field.as_str()))
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect(),
+            fields,
+            password_changed: password.password_changed,
+            strength: password.strength.clone().or_else(|| {
+                password
+                    .password()
+                    .map(|password| strength::estimate(password))
+            }),
+        }
+    }
+}
+
+/// A password file as it was when its summary was taken
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    file: PathBuf,
+    modified: SystemTime,
+    size: u64,
+    summary: PasswordSummary,
+}
+
+/// Ciphered summaries of the passwords of a vault, so that listing them only deciphers the files
+/// changed since the last time.
+///
+/// It is a cache kept next to the user directory, out of its git history, and is rebuilt
+/// whenever it can't be read.
+#[derive(Serialize, Deserialize)]
+struct EntryIndex {
+    version: u32,
+    entries: HashMap<String, IndexEntry>,
+}
+
+impl Default for EntryIndex {
+    fn default() -> Self {
+        EntryIndex {
+            version: INDEX_VERSION,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl EntryIndex {
+    fn read(vault: &Vault) -> Self {
+        let path = index_path(vault);
+
+        fs::read(&path)
+            .ok()
+            .and_then(|content| open(&vault.key, &content).ok())
+            .and_then(|data| serde_json::from_slice::<EntryIndex>(&data).ok())
+            .filter(|index| index.version == INDEX_VERSION)
+            .unwrap_or_default()
+    }
+
+    fn write(&self, vault: &Vault) -> Result<(), PassyError> {
+        let path = index_path(vault);
+        let data =
+            serde_json::to_vec(self).map_err(|_| PassyError::FailedToWriteIndex(path.clone()))?;
+        let content =
+            seal(&vault.key, &data).map_err(|_| PassyError::FailedToWriteIndex(path.clone()))?;
+        atomic::write(&path, content).map_err(|_| PassyError::FailedToWriteIndex(path))
+    }
+}
+
+fn index_path(vault: &Vault) -> PathBuf {
+    vault.path.with_file_name(index_file_name(&vault.username))
+}
+
+/// Name of the index of `username`, kept in the appdata directory
+pub fn index_file_name(username: &str) -> String {
+    format!(".{}.index", username)
+}
+
+/// Modification time and size of `file`, which tell whether it changed since it was indexed
+fn file_stamp(file: &Path) -> Result<(SystemTime, u64), PassyError> {
+    fs::metadata(file)
+        .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+        .map_err(|_| {
+            PassyError::FailedToReadPassword(
+                file.to_owned(),
+                PasswordReadFailedReason::FileNotFound,
+            )
+        })
+}
+
+/// Summaries of every password of the vault, only the files changed since they were last indexed
+/// are deciphered
+pub fn summaries(vault: &Vault) -> Result<Vec<PasswordSummary>, PassyError> {
+    let mut cached = EntryIndex::read(vault);
+
+    let entries = list_entries(vault)?;
+    // Passwords removed since the index was written leave it with more entries
+    let mut changed = entries.len() != cached.entries.len();
+
+    let mut updated = HashMap::new();
+    for (path, file) in entries {
+        let (modified, size) = file_stamp(&file)?;
+
+        let entry = match cached.entries.remove(&path) {
+            Some(entry)
+                if entry.file == file && entry.modified == modified && entry.size == size =>
+            {
+                entry
+            }
+            _ => {
+                changed = true;
+                let password = Password::read_file(vault, &file, path.clone())?;
+                IndexEntry {
+                    summary: PasswordSummary::from(&password),
+                    file,
+                    modified,
+                    size,
+                }
+            }
+        };
+
+        updated.insert(path, entry);
+    }
+
+    let index = EntryIndex {
+        entries: updated,
+        ..EntryIndex::default()
+    };
+    if changed {
+        index.write(vault)?;
+    }
+
+    let mut summaries: Vec<PasswordSummary> = index
+        .entries
+        .into_values()
+        .map(|entry| entry.summary)
+        .collect();
+    summaries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(summaries)
+}
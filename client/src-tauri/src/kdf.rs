@@ -0,0 +1,79 @@
+use std::{fs, path::Path};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use zeroize::Zeroizing;
+
+use crate::{error::PassyError, secret::SecretKey};
+
+const SALT_LEN: usize = 16;
+const KEY_FILE_LEN: usize = 64;
+
+/// SHA-256 hash of a key file's content
+pub type KeyFileHash = [u8; 32];
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KdfParams {
+    salt: Vec<u8>,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl KdfParams {
+    pub fn generate() -> Self {
+        let mut salt = vec![0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+
+        KdfParams {
+            salt,
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+
+    /// Derives a key from `password`, the key file hash is used as the Argon2 secret if any
+    pub fn derive_key(
+        &self,
+        password: &str,
+        key_file: Option<&KeyFileHash>,
+    ) -> Result<SecretKey, PassyError> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|_| PassyError::KeyDerivationFailed)?;
+
+        let argon2 = match key_file {
+            Some(hash) => {
+                Argon2::new_with_secret(hash, Algorithm::Argon2id, Version::V0x13, params)
+                    .map_err(|_| PassyError::KeyDerivationFailed)?
+            }
+            None => Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+        };
+
+        let mut key = Zeroizing::new([0; 32]);
+        argon2
+            .hash_password_into(password.as_bytes(), &self.salt, key.as_mut())
+            .map_err(|_| PassyError::KeyDerivationFailed)?;
+
+        Ok(key)
+    }
+}
+
+pub fn read_key_file(path: &Path) -> Result<KeyFileHash, PassyError> {
+    let content = fs::read(path).map_err(|_| PassyError::FailedToReadKeyFile(path.to_owned()))?;
+    Ok(Sha256::digest(content).into())
+}
+
+pub fn generate_key_file(path: &Path) -> Result<(), PassyError> {
+    let mut content = vec![0; KEY_FILE_LEN];
+    OsRng.fill_bytes(&mut content);
+    fs::write(path, content).map_err(|_| PassyError::FailedToWriteKeyFile(path.to_owned()))
+}
+
+/// Fingerprint stored in the vault config, used to tell a wrong key file from a wrong password
+pub fn key_file_fingerprint(hash: &KeyFileHash) -> Vec<u8> {
+    Sha256::digest(hash).to_vec()
+}
@@ -0,0 +1,199 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use crate::{error::PassyError, names::random_name, vault::Vault};
+
+/// Time to wait for another process to release a vault before giving up
+const LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+const LOCK_RETRY: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LockMode {
+    /// Any number of processes can read the vault at once
+    Shared,
+    /// A single process writes the vault, no one else can read it meanwhile
+    Exclusive,
+}
+
+impl LockMode {
+    fn name(self) -> &'static str {
+        match self {
+            LockMode::Shared => "shared",
+            LockMode::Exclusive => "exclusive",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "shared" => Some(LockMode::Shared),
+            "exclusive" => Some(LockMode::Exclusive),
+            _ => None,
+        }
+    }
+
+    /// Whether a lock with this mode can't be held along with one with `other`
+    fn conflicts_with(self, other: LockMode) -> bool {
+        self == LockMode::Exclusive || other == LockMode::Exclusive
+    }
+}
+
+/// Advisory lock of a user directory, shared by readers and exclusive to writers, released when
+/// dropped.
+///
+/// The lock itself is taken by the OS on `.{username}.lock` in the appdata directory, so it is
+/// released even if its process dies. Each holder also leaves a file named after its PID in
+/// `.{username}.holders`, to tell who holds the vault. Holder files are locked by the OS as well,
+/// the ones that can be locked by someone else were left by dead processes.
+pub struct VaultLock {
+    _file: File,
+    holder: PathBuf,
+    _holder_file: File,
+}
+
+impl VaultLock {
+    pub fn shared(vault: &Vault) -> Result<Self, PassyError> {
+        VaultLock::acquire(appdata_of(vault), &vault.username, LockMode::Shared)
+    }
+
+    pub fn exclusive(vault: &Vault) -> Result<Self, PassyError> {
+        VaultLock::acquire(appdata_of(vault), &vault.username, LockMode::Exclusive)
+    }
+
+    /// Locks the directory of `username`, waiting a bit for other processes to release it
+    pub fn acquire(appdata: &Path, username: &str, mode: LockMode) -> Result<Self, PassyError> {
+        VaultLock::acquire_within(appdata, username, mode, LOCK_TIMEOUT)
+    }
+
+    /// Locks the directory of `username` if no other process holds it, without waiting
+    pub fn try_acquire(
+        appdata: &Path,
+        username: &str,
+        mode: LockMode,
+    ) -> Result<Option<Self>, PassyError> {
+        match VaultLock::acquire_within(appdata, username, mode, Duration::ZERO) {
+            Ok(lock) => Ok(Some(lock)),
+            Err(PassyError::VaultLocked(_, _)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn acquire_within(
+        appdata: &Path,
+        username: &str,
+        mode: LockMode,
+        timeout: Duration,
+    ) -> Result<Self, PassyError> {
+        let failed = || PassyError::FailedToLockVault(username.to_string());
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(appdata.join(format!(".{}.lock", username)))
+            .map_err(|_| failed())?;
+        let holders = appdata.join(format!(".{}.holders", username));
+
+        let start = Instant::now();
+        loop {
+            let res = match mode {
+                LockMode::Shared => file.try_lock_shared(),
+                LockMode::Exclusive => file.try_lock(),
+            };
+
+            match res {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                    thread::sleep(LOCK_RETRY)
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(PassyError::VaultLocked(
+                        username.to_string(),
+                        holder_pid(&holders, mode),
+                    ))
+                }
+                Err(TryLockError::Error(_)) => return Err(failed()),
+            }
+        }
+
+        remove_stale_holders(&holders).map_err(|_| failed())?;
+
+        fs::create_dir_all(&holders).map_err(|_| failed())?;
+        let name = format!("{}.{}.{}", process::id(), random_name(), mode.name());
+        let holder = holders.join(&name);
+
+        // Unlocked holder files are taken for stale ones by other processes, the file only gets
+        // its name once locked
+        let temp = holders.join(format!(".{}", name));
+        let holder_file = File::create(&temp).map_err(|_| failed())?;
+        if holder_file.try_lock().is_err() || fs::rename(&temp, &holder).is_err() {
+            fs::remove_file(&temp).ok();
+            return Err(failed());
+        }
+
+        Ok(VaultLock {
+            _file: file,
+            holder,
+            _holder_file: holder_file,
+        })
+    }
+}
+
+impl Drop for VaultLock {
+    fn drop(&mut self) {
+        fs::remove_file(&self.holder).ok();
+    }
+}
+
+fn appdata_of(vault: &Vault) -> &Path {
+    vault.path.parent().unwrap_or(Path::new("."))
+}
+
+/// PID and lock mode of the holder recorded in `file_name`
+fn parse_holder(file_name: &str) -> Option<(u32, LockMode)> {
+    let (pid, rest) = file_name.split_once('.')?;
+    let (_, mode) = rest.rsplit_once('.')?;
+    Some((pid.parse().ok()?, LockMode::from_name(mode)?))
+}
+
+/// Whether the process that left the `holder` file still holds its lock, if the file can be
+/// locked it is released as soon as it is closed
+fn is_alive(holder: &Path) -> bool {
+    matches!(
+        File::open(holder).map(|file| file.try_lock()),
+        Ok(Err(TryLockError::WouldBlock))
+    )
+}
+
+/// PID of a living process holding a lock that prevents taking one with `mode`
+fn holder_pid(holders: &Path, mode: LockMode) -> Option<u32> {
+    fs::read_dir(holders)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let (pid, holder_mode) = parse_holder(&entry.file_name().to_string_lossy())?;
+            (mode.conflicts_with(holder_mode) && is_alive(&entry.path())).then_some(pid)
+        })
+        .next()
+}
+
+/// Removes the files of holders whose processes died without releasing the vault
+fn remove_stale_holders(holders: &Path) -> std::io::Result<()> {
+    if !Path::exists(holders) {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(holders)?.flatten() {
+        // Hidden files are holders being created, not locked yet
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !hidden && !is_alive(&entry.path()) {
+            // Another process may have removed it first
+            fs::remove_file(entry.path()).ok();
+        }
+    }
+
+    Ok(())
+}
@@ -2,40 +2,69 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod appdata;
+mod atomic;
+mod breach;
 mod crypto;
 mod error;
+mod generator;
+mod git;
+mod header;
+mod index;
+mod kdf;
+mod lock;
+mod migration;
+mod names;
+mod otp;
 mod password;
 mod plugin;
+mod report;
+mod revisions;
+mod secret;
+mod session;
+mod strength;
+mod sync;
+mod trash;
 mod user;
+mod vault;
+mod watcher;
 
 use std::{
     collections::HashMap,
-    fs,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    thread,
+    time::Duration,
 };
 
 use appdata::{get_appdata_path, read_appdata};
+use breach::Breach;
+use crypto::CipherSuite;
 use error::{PassyError, PluginErrorKind};
-use password::{Metadata, Password};
+use generator::GeneratorOptions;
+use git::HistoryEntry;
+use index::PasswordSummary;
+use kdf::read_key_file;
+use lock::{LockMode, VaultLock};
+use otp::OtpCode;
+use password::{migrate_legacy_files, Metadata, Password, PASSWORD_FIELD};
 use plugin::{AppState, JSResult, Plugin, PluginManifest, PluginPayload};
+use report::{ReportOptions, SecurityReport};
+use revisions::{FieldDiff, RevisionInfo};
+use secret::SecretString;
 use serde::Serialize;
-use tauri::{Manager, State};
-use user::{AppUser, SerializableAppUser, Username};
-
-type CurrentUserState = Mutex<Option<CurrentUser>>;
-
-#[derive(Clone)]
-struct CurrentUser {
-    username: Username,
-    key: [u8; 32],
-}
+use session::{CurrentUserState, Session};
+use sync::{ConflictResolution, PullResult};
+use tauri::{Manager, State, Window};
+use trash::TrashedPassword;
+use user::{AppUser, SerializableAppUser};
+use vault::{Vault, VaultConfig, VaultOptions};
+use watcher::VaultWatcher;
 
 #[derive(Serialize)]
 struct AccountData {
     plugins: HashMap<String, PluginManifest>,
     appdata_path: String,
-    passwords: Vec<Password>,
+    passwords: Vec<PasswordSummary>,
 }
 
 impl Into<SerializableAppUser> for &AppUser {
@@ -56,57 +85,59 @@ fn create_user(
     appdata: State<PathBuf>,
     users_state: State<Mutex<Vec<AppUser>>>,
     name: String,
+    password: String,
+    key_file: Option<String>,
+    options: Option<VaultOptions>,
 ) -> Result<(), String> {
     let mut users = users_state.lock().unwrap();
-    let user = AppUser::create(&appdata, name)?;
+    let user = AppUser::create(
+        &appdata,
+        name,
+        &password,
+        key_file.as_deref().map(Path::new),
+        options.unwrap_or_default(),
+    )?;
     users.push(user);
     Ok(())
 }
 
-fn check_login(current_user_state: State<CurrentUserState>) -> Result<CurrentUser, PassyError> {
-    Ok(current_user_state
-        .lock()
-        .unwrap()
-        .as_ref()
-        .ok_or(PassyError::NotLoggedIn)?
-        .clone())
+fn check_login(current_user_state: State<CurrentUserState>) -> Result<Vault, PassyError> {
+    let mut current_user = current_user_state.lock().unwrap();
+    let session = current_user.as_mut().ok_or(PassyError::NotLoggedIn)?;
+    session.touch();
+    Ok(session.vault.clone())
 }
 
-fn recursive_pwd_read(
-    key: &[u8; 32],
-    user_path: &Path,
-    path: String,
-) -> Result<Vec<Password>, PassyError> {
-    let curr_path = user_path.join(path.clone());
-    fs::read_dir(&curr_path)
-        .map_err(|_| PassyError::FailedToReadDir(curr_path.to_owned()))?
-        .into_iter()
-        .filter_map(|res| {
-            res.ok().and_then(|entry| {
-                if entry.path().is_dir() {
-                    Some(recursive_pwd_read(
-                        key,
-                        user_path,
-                        path.clone()
-                            + entry.file_name().to_string_lossy().to_string().as_str()
-                            + "/",
-                    ))
-                } else {
-                    let lossy_filename = entry.file_name().to_string_lossy().to_string();
-
-                    if lossy_filename.ends_with(".passy") {
-                        Some(
-                            Password::read(key, user_path, &(path.clone() + &lossy_filename))
-                                .and_then(|pwd| Ok(vec![pwd])),
-                        )
-                    } else {
-                        None
-                    }
-                }
-            })
-        })
-        .collect::<Result<Vec<_>, _>>()
-        .and_then(|r| Ok(r.into_iter().flat_map(|v| v).collect()))
+/// Logged in vault, for reads the user didn't ask for which don't postpone the auto lock
+fn current_vault(current_user_state: State<CurrentUserState>) -> Result<Vault, PassyError> {
+    let current_user = current_user_state.lock().unwrap();
+    let session = current_user.as_ref().ok_or(PassyError::NotLoggedIn)?;
+    Ok(session.vault.clone())
+}
+
+/// Session of `vault`, whose files are watched for changes sent to `window`
+fn open_session(vault: Vault, window: Window) -> Session {
+    // Live updates are a convenience, the vault can still be used without them
+    let watcher = VaultWatcher::start(&vault, window).ok();
+    Session::new(vault, watcher)
+}
+
+/// Logged in vault, along with a shared lock of its directory for commands only reading it
+fn read_access(
+    current_user_state: State<CurrentUserState>,
+) -> Result<(Vault, VaultLock), PassyError> {
+    let vault = check_login(current_user_state)?;
+    let lock = VaultLock::shared(&vault)?;
+    Ok((vault, lock))
+}
+
+/// Logged in vault, along with an exclusive lock of its directory for commands writing it
+fn write_access(
+    current_user_state: State<CurrentUserState>,
+) -> Result<(Vault, VaultLock), PassyError> {
+    let vault = check_login(current_user_state)?;
+    let lock = VaultLock::exclusive(&vault)?;
+    Ok((vault, lock))
 }
 
 #[tauri::command]
@@ -115,18 +146,14 @@ fn get_user_data(
     plugins_state: State<Arc<Mutex<HashMap<String, Plugin>>>>,
     current_user_state: State<CurrentUserState>,
 ) -> Result<AccountData, String> {
-    let current_user = check_login(current_user_state)?;
-    let username = current_user.username;
+    let (vault, _lock) = read_access(current_user_state)?;
 
-    let user_path = appdata.join(username.clone());
-
-    if !Path::exists(&user_path) {
-        return Err(PassyError::UserNotFound(username.clone()).into());
+    if !Path::exists(&vault.path) {
+        return Err(PassyError::UserNotFound(vault.username.clone()).into());
     }
 
-    let key = &current_user.key;
-
-    let passwords = recursive_pwd_read(key, &user_path, "".to_string())?;
+    // Passwords are only fully deciphered once opened, with `get_password`
+    let passwords = index::summaries(&vault)?;
 
     let plugins = Plugin::init_loader(&appdata)?;
 
@@ -143,33 +170,46 @@ fn get_user_data(
     Ok(AccountData {
         plugins: plugins_manifests,
         passwords,
-        appdata_path: user_path.to_string_lossy().to_string(),
+        appdata_path: vault.path.to_string_lossy().to_string(),
     })
 }
 
 #[tauri::command]
 fn create_password(
-    appdata: State<PathBuf>,
     users_state: State<Mutex<Vec<AppUser>>>,
     current_user_state: State<CurrentUserState>,
     path: String,
+    generate: Option<GeneratorOptions>,
 ) -> Result<Password, String> {
     let users = users_state.lock().unwrap();
 
-    let current_user = check_login(current_user_state)?;
-    let key = &current_user.key;
-    let username = current_user.username.as_str();
+    let (vault, _lock) = write_access(current_user_state)?;
+
+    let mut data = Metadata::default();
+    if let Some(options) = generate {
+        data.get_mut()
+            .insert(PASSWORD_FIELD.to_string(), options.generate()?);
+    }
 
-    let password = Password::new(key, &appdata, username, path, Metadata::default())?;
+    let password = Password::new(&vault, path, data)?;
+    git::commit(&vault, &git::describe(&vault, "Create", &password.path))?;
     Ok(password)
 }
 
+#[tauri::command]
+fn generate_password(options: Option<GeneratorOptions>) -> Result<SecretString, String> {
+    Ok(options.unwrap_or_default().generate()?)
+}
+
 #[tauri::command]
 fn login(
+    window: Window,
     username: String,
+    appdata: State<PathBuf>,
     current_user_state: State<CurrentUserState>,
     users_state: State<Mutex<Vec<AppUser>>>,
     password: String,
+    key_file: Option<String>,
 ) -> Result<(), String> {
     let users = users_state.lock().unwrap();
 
@@ -178,40 +218,580 @@ fn login(
         .find(|u| u.name == username)
         .ok_or(PassyError::UserNotFound(username))?;
 
+    // Older vaults are upgraded when unlocked
+    let _lock = VaultLock::acquire(&appdata, &user.name, LockMode::Exclusive)?;
+    let vault = vault::unlock(
+        &appdata,
+        &user.name,
+        &password,
+        key_file.as_deref().map(Path::new),
+    )?;
+
+    migrate_legacy_files(&vault)?;
+    git::commit(&vault, "Upgrade vault format")?;
+
+    if trash::purge_expired(&vault)? {
+        git::commit(&vault, "Purge trash")?;
+    }
+
     let mut current_user = current_user_state.lock().unwrap();
 
-    *current_user = Some(CurrentUser {
-        username: user.name.clone(),
-        key: [0; 32],
-    });
+    *current_user = Some(open_session(vault, window));
 
     Ok(())
 }
 
 #[tauri::command]
-fn update_password(
+fn lock(current_user_state: State<CurrentUserState>) -> Result<(), String> {
+    session::lock(&current_user_state);
+    Ok(())
+}
+
+#[tauri::command]
+fn logout(
     current_user_state: State<CurrentUserState>,
+    plugins_state: State<Arc<Mutex<HashMap<String, Plugin>>>>,
+) -> Result<(), String> {
+    session::lock(&current_user_state);
+    plugins_state.lock().unwrap().clear();
+    Ok(())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn change_master_password(
+    window: Window,
     appdata: State<PathBuf>,
-    password: Password,
+    current_user_state: State<CurrentUserState>,
+    old_password: String,
+    new_password: String,
+    key_file: Option<String>,
+    new_key_file: Option<String>,
+    remove_key_file: bool,
 ) -> Result<(), String> {
-    let current_user = check_login(current_user_state)?;
-    password.write(&current_user.key, &appdata, &current_user.username)?;
+    let mut current_user = current_user_state.lock().unwrap();
+    let username = current_user
+        .as_ref()
+        .ok_or(PassyError::NotLoggedIn)?
+        .vault
+        .username
+        .clone();
+    let _lock = VaultLock::acquire(&appdata, &username, LockMode::Exclusive)?;
+
+    // Without a new key file the current one stays required, unless its removal is asked for
+    let new_key_file = match (new_key_file.as_deref(), key_file.as_deref()) {
+        (Some(path), _) => Some(read_key_file(Path::new(path))?),
+        (None, _) if remove_key_file => None,
+        (None, path) => path
+            .map(|path| read_key_file(Path::new(path)))
+            .transpose()?,
+    };
+
+    let mut vault = vault::unlock(
+        &appdata,
+        &username,
+        &old_password,
+        key_file.as_deref().map(Path::new),
+    )?;
+
+    vault
+        .config
+        .rewrap(&vault.key, &new_password, new_key_file.as_ref())?;
+    vault.config.write(&vault.path)?;
+    git::commit(&vault, "Change master password")?;
+
+    *current_user = Some(open_session(vault, window));
+
+    Ok(())
+}
+
+/// Ciphers the whole vault again with new settings, the data key stays the same
+fn reencrypt_current_vault(
+    window: Window,
+    appdata: &Path,
+    current_user_state: State<CurrentUserState>,
+    configure: impl FnOnce(&mut VaultConfig),
+    message: &str,
+) -> Result<(), PassyError> {
+    let mut current_user = current_user_state.lock().unwrap();
+    let vault = &current_user.as_ref().ok_or(PassyError::NotLoggedIn)?.vault;
+    let _lock = VaultLock::exclusive(vault)?;
+
+    let mut config = VaultConfig::read(&vault.path)?;
+    configure(&mut config);
+
+    let vault = vault::reencrypt(appdata, vault, vault.key.clone(), config)?;
+    git::commit(&vault, message)?;
+
+    *current_user = Some(open_session(vault, window));
+
     Ok(())
 }
 
 #[tauri::command]
-fn delete_password(
+fn change_cipher(
+    window: Window,
+    appdata: State<PathBuf>,
     current_user_state: State<CurrentUserState>,
+    cipher: CipherSuite,
+) -> Result<(), String> {
+    reencrypt_current_vault(
+        window,
+        &appdata,
+        current_user_state,
+        |config| config.cipher = cipher,
+        "Change cipher",
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+fn set_hidden_names(
+    window: Window,
     appdata: State<PathBuf>,
+    current_user_state: State<CurrentUserState>,
+    hidden_names: bool,
+) -> Result<(), String> {
+    reencrypt_current_vault(
+        window,
+        &appdata,
+        current_user_state,
+        |config| config.hidden_names = hidden_names,
+        if hidden_names {
+            "Hide passwords names"
+        } else {
+            "Show passwords names"
+        },
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+fn set_auto_lock(
+    current_user_state: State<CurrentUserState>,
+    seconds: Option<u64>,
+) -> Result<(), String> {
+    let mut current_user = current_user_state.lock().unwrap();
+    let session = current_user.as_mut().ok_or(PassyError::NotLoggedIn)?;
+    let _lock = VaultLock::exclusive(&session.vault)?;
+
+    let mut config = VaultConfig::read(&session.vault.path)?;
+    config.auto_lock = seconds;
+    config.write(&session.vault.path)?;
+
+    session.vault.config = config;
+    session.touch();
+    git::commit(&session.vault, "Change auto lock delay")?;
+
+    Ok(())
+}
+
+/// Enables or disables the git history of the vault, disabling it keeps the past commits
+#[tauri::command]
+fn set_git_history(
+    current_user_state: State<CurrentUserState>,
+    enabled: bool,
+) -> Result<(), String> {
+    let mut current_user = current_user_state.lock().unwrap();
+    let session = current_user.as_mut().ok_or(PassyError::NotLoggedIn)?;
+    let _lock = VaultLock::exclusive(&session.vault)?;
+
+    let mut config = VaultConfig::read(&session.vault.path)?;
+    config.git = enabled;
+    config.write(&session.vault.path)?;
+
+    session.vault.config = config;
+    session.touch();
+
+    if enabled {
+        git::init(&session.vault)?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+fn set_trash_retention(
+    current_user_state: State<CurrentUserState>,
+    days: Option<u64>,
+) -> Result<(), String> {
+    let mut current_user = current_user_state.lock().unwrap();
+    let session = current_user.as_mut().ok_or(PassyError::NotLoggedIn)?;
+    let _lock = VaultLock::exclusive(&session.vault)?;
+
+    let mut config = VaultConfig::read(&session.vault.path)?;
+    config.trash_retention = days;
+    config.write(&session.vault.path)?;
+
+    session.vault.config = config;
+    session.touch();
+    git::commit(&session.vault, "Change trash retention")?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn set_history_depth(
+    current_user_state: State<CurrentUserState>,
+    depth: usize,
+) -> Result<(), String> {
+    let mut current_user = current_user_state.lock().unwrap();
+    let session = current_user.as_mut().ok_or(PassyError::NotLoggedIn)?;
+    let _lock = VaultLock::exclusive(&session.vault)?;
+
+    let mut config = VaultConfig::read(&session.vault.path)?;
+    config.history_depth = depth;
+    config.write(&session.vault.path)?;
+
+    session.vault.config = config;
+    session.touch();
+    git::commit(&session.vault, "Change history depth")?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn set_sync_remote(
+    current_user_state: State<CurrentUserState>,
+    remote: Option<String>,
+) -> Result<(), String> {
+    let mut current_user = current_user_state.lock().unwrap();
+    let session = current_user.as_mut().ok_or(PassyError::NotLoggedIn)?;
+    let _lock = VaultLock::exclusive(&session.vault)?;
+
+    let mut config = VaultConfig::read(&session.vault.path)?;
+    config.remote = remote;
+    config.write(&session.vault.path)?;
+
+    session.vault.config = config;
+    session.touch();
+    git::commit(&session.vault, "Change sync remote")?;
+
+    Ok(())
+}
+
+#[tauri::command]
+fn sync_push(current_user_state: State<CurrentUserState>) -> Result<(), String> {
+    let (vault, lock) = write_access(current_user_state)?;
+    git::commit(&vault, "Save changes before sync")?;
+    drop(lock);
+
+    // The vault stays usable while the commits are sent
+    sync::push(&vault)?;
+    Ok(())
+}
+
+/// Pulls the changes of the remote, conflicts are returned until `resolutions` covers them all
+#[tauri::command]
+fn sync_pull(
+    current_user_state: State<CurrentUserState>,
+    resolutions: Option<Vec<ConflictResolution>>,
+) -> Result<PullResult, String> {
+    // The vault stays usable while the changes of the remote are received
+    let vault = check_login(current_user_state.clone())?;
+    sync::fetch(&vault)?;
+
+    let mut current_user = current_user_state.lock().unwrap();
+    let session = current_user.as_mut().ok_or(PassyError::NotLoggedIn)?;
+    let _lock = VaultLock::exclusive(&session.vault)?;
+    session.touch();
+
+    let result = sync::pull(&session.vault, &resolutions.unwrap_or_default())?;
+
+    // Settings may come from the remote
+    session.vault.config = VaultConfig::read(&session.vault.path)?;
+
+    Ok(result)
+}
+
+#[tauri::command]
+fn get_vault_history(
+    current_user_state: State<CurrentUserState>,
+) -> Result<Vec<HistoryEntry>, String> {
+    let (vault, _lock) = read_access(current_user_state)?;
+    Ok(git::vault_history(&vault)?)
+}
+
+#[tauri::command]
+fn get_password_history(
+    current_user_state: State<CurrentUserState>,
+    password_path: String,
+) -> Result<Vec<HistoryEntry>, String> {
+    let (vault, _lock) = read_access(current_user_state)?;
+    Ok(git::password_history(&vault, &password_path)?)
+}
+
+#[tauri::command]
+fn restore_password_version(
+    current_user_state: State<CurrentUserState>,
+    password_path: String,
+    commit: String,
+) -> Result<Password, String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+    Ok(git::restore_version(&vault, &password_path, &commit)?)
+}
+
+#[tauri::command]
+fn check_breaches(
+    current_user_state: State<CurrentUserState>,
+    hash_list: String,
+) -> Result<Vec<Breach>, String> {
+    let (vault, _lock) = read_access(current_user_state)?;
+    Ok(breach::check_vault(&vault, Path::new(&hash_list))?)
+}
+
+#[tauri::command]
+fn security_report(
+    current_user_state: State<CurrentUserState>,
+    options: Option<ReportOptions>,
+) -> Result<SecurityReport, String> {
+    let (vault, _lock) = read_access(current_user_state)?;
+    Ok(report::security_report(
+        &vault,
+        &options.unwrap_or_default(),
+    )?)
+}
+
+/// Deciphers the password at `path`, `refresh` is set when it is read again because it changed
+/// rather than because the user opened it
+#[tauri::command]
+fn get_password(
+    current_user_state: State<CurrentUserState>,
+    path: String,
+    refresh: Option<bool>,
+) -> Result<Password, String> {
+    let vault = if refresh.unwrap_or(false) {
+        current_vault(current_user_state)?
+    } else {
+        check_login(current_user_state)?
+    };
+    let _lock = VaultLock::shared(&vault)?;
+    let mut password = Password::read(&vault, &path)?;
+    password.update_strength();
+    Ok(password)
+}
+
+#[tauri::command]
+fn get_otp_code(
+    current_user_state: State<CurrentUserState>,
+    password_path: String,
+) -> Result<OtpCode, String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+    let code = otp::get_code(&vault, &password_path)?;
+    // Only HOTP entries change, their counter is advanced
+    git::commit(
+        &vault,
+        &git::describe(&vault, "Advance counter of", &password_path),
+    )?;
+    Ok(code)
+}
+
+#[tauri::command]
+fn import_otp_migration(
+    current_user_state: State<CurrentUserState>,
+    uri: String,
+    folder: Option<String>,
+) -> Result<Vec<Password>, String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+    let res = migration::import_uri(&vault, &uri, folder.as_deref().unwrap_or_default());
+    // Entries written before a failure are committed too
+    git::commit(&vault, "Import one-time codes")?;
+    Ok(res?)
+}
+
+#[tauri::command]
+fn import_otp_migration_image(
+    current_user_state: State<CurrentUserState>,
+    path: String,
+    folder: Option<String>,
+) -> Result<Vec<Password>, String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+    let res = migration::import_image(
+        &vault,
+        Path::new(&path),
+        folder.as_deref().unwrap_or_default(),
+    );
+    // Entries written before a failure are committed too
+    git::commit(&vault, "Import one-time codes")?;
+    Ok(res?)
+}
+
+#[tauri::command]
+fn generate_key_file(path: String) -> Result<(), String> {
+    kdf::generate_key_file(Path::new(&path))?;
+    Ok(())
+}
+
+#[tauri::command]
+fn update_password(
+    current_user_state: State<CurrentUserState>,
+    mut password: Password,
+) -> Result<Password, String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+
+    let previous = Password::read(&vault, &password.path).ok();
+    password.track_password_change(previous.as_ref());
+
+    if let Some(previous) = previous {
+        if previous.data().get() != password.data().get() {
+            revisions::record(&vault, previous)?;
+        }
+    }
+
+    password.write(&vault)?;
+    git::commit(&vault, &git::describe(&vault, "Update", &password.path))?;
+    password.update_strength();
+    Ok(password)
+}
+
+#[tauri::command]
+fn rename_password(
+    current_user_state: State<CurrentUserState>,
+    password_path: String,
+    new_path: String,
+) -> Result<Password, String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+
+    if Password::exists(&vault, &new_path)? {
+        return Err(PassyError::PasswordAlreadyExists(new_path).into());
+    }
+
+    let mut password = Password::read(&vault, &password_path)?;
+
+    // Passwords are bound to their path, so it has to be ciphered again
+    password.path = new_path;
+    password.write(&vault)?;
+    revisions::copy(&vault, &vault, &password_path, &password.path)?;
+    Password::delete(&vault, password_path.clone())?;
+    git::commit(
+        &vault,
+        &git::describe(
+            &vault,
+            "Rename",
+            &format!("{} to {}", password_path, password.path),
+        ),
+    )?;
+    password.update_strength();
+
+    Ok(password)
+}
+
+#[tauri::command]
+fn delete_password(
+    current_user_state: State<CurrentUserState>,
     password_path: String,
 ) -> Result<(), String> {
-    let current_user = check_login(current_user_state)?;
-    Password::delete(password_path, &current_user.username, &appdata)?;
+    let (vault, _lock) = write_access(current_user_state)?;
+    trash::move_to_trash(&vault, &password_path)?;
+    git::commit(&vault, &git::describe(&vault, "Delete", &password_path))?;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_password_revisions(
+    current_user_state: State<CurrentUserState>,
+    password_path: String,
+) -> Result<Vec<RevisionInfo>, String> {
+    let (vault, _lock) = read_access(current_user_state)?;
+    Ok(revisions::list(&vault, &password_path)?)
+}
+
+/// Fields changed from revision `from` to revision `to`, or to the current version
+#[tauri::command]
+fn diff_password_revisions(
+    current_user_state: State<CurrentUserState>,
+    password_path: String,
+    from: u64,
+    to: Option<u64>,
+) -> Result<Vec<FieldDiff>, String> {
+    let (vault, _lock) = read_access(current_user_state)?;
+    Ok(revisions::diff(&vault, &password_path, from, to)?)
+}
+
+#[tauri::command]
+fn restore_password_revision(
+    current_user_state: State<CurrentUserState>,
+    password_path: String,
+    revision: u64,
+) -> Result<Password, String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+    let password = revisions::restore(&vault, &password_path, revision)?;
+    git::commit(&vault, &git::describe(&vault, "Restore", &password_path))?;
+    Ok(password)
+}
+
+#[tauri::command]
+fn list_trash(current_user_state: State<CurrentUserState>) -> Result<Vec<TrashedPassword>, String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+
+    if trash::purge_expired(&vault)? {
+        git::commit(&vault, "Purge trash")?;
+    }
+
+    Ok(trash::list(&vault)?)
+}
+
+#[tauri::command]
+fn restore_password(
+    current_user_state: State<CurrentUserState>,
+    id: String,
+) -> Result<Password, String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+    let password = trash::restore(&vault, &id)?;
+    git::commit(&vault, &git::describe(&vault, "Restore", &password.path))?;
+    Ok(password)
+}
+
+#[tauri::command]
+fn empty_trash(current_user_state: State<CurrentUserState>) -> Result<(), String> {
+    let (vault, _lock) = write_access(current_user_state)?;
+    trash::empty(&vault)?;
+    git::commit(&vault, "Empty trash")?;
     Ok(())
 }
 
+/// Cleans up after instances of the app that were interrupted, e.g. by a crash. Vaults used by
+/// another instance are left as they are until the next start.
+///
+/// The cleanup is best-effort: a failure is reported and retried at the next start, but it
+/// doesn't keep the app from starting.
+fn recover_appdata(appdata: &Path) {
+    if let Err(e) = vault::recover_interrupted_swaps(appdata) {
+        eprintln!(
+            "Failed to recover interrupted vault swaps: {}",
+            String::from(e)
+        );
+    }
+    let users = match read_appdata(appdata.to_owned()) {
+        Ok(users) => users.into_inner().unwrap(),
+        Err(e) => {
+            eprintln!("Failed to clean up the vaults: {}", String::from(e));
+            return;
+        }
+    };
+    for user in users {
+        if let Err(e) = clean_up_vault(appdata, &user) {
+            eprintln!(
+                "Failed to clean up the vault of {}: {}",
+                user.name,
+                String::from(e)
+            );
+        }
+    }
+}
+
+fn clean_up_vault(appdata: &Path, user: &AppUser) -> Result<(), PassyError> {
+    let _lock = match VaultLock::try_acquire(appdata, &user.name, LockMode::Exclusive)? {
+        Some(lock) => lock,
+        None => return Ok(()),
+    };
+    atomic::remove_temp_files(&user.path)?;
+    atomic::remove_temp_files_of(&appdata.join(index::index_file_name(&user.name)))
+}
+
 fn main() {
     let appdata_path = get_appdata_path().unwrap();
+    recover_appdata(&appdata_path);
     let users = read_appdata(appdata_path.clone()).unwrap();
 
     tauri::Builder::default()
@@ -223,13 +803,54 @@ fn main() {
             get_users,
             create_user,
             get_user_data,
+            get_password,
             create_password,
+            generate_password,
             login,
+            lock,
+            logout,
+            change_master_password,
+            change_cipher,
+            set_hidden_names,
+            set_auto_lock,
+            set_git_history,
+            get_vault_history,
+            get_password_history,
+            restore_password_version,
+            set_sync_remote,
+            sync_push,
+            sync_pull,
+            check_breaches,
+            security_report,
+            get_otp_code,
+            import_otp_migration,
+            import_otp_migration_image,
+            generate_key_file,
             update_password,
+            rename_password,
             delete_password,
+            get_password_revisions,
+            diff_password_revisions,
+            restore_password_revision,
+            set_history_depth,
+            list_trash,
+            restore_password,
+            empty_trash,
+            set_trash_retention,
         ])
         .setup(|app| {
             let main_window = app.get_window("main").unwrap();
+
+            let app_handle = app.app_handle();
+            let locked_window = main_window.clone();
+            thread::spawn(move || loop {
+                thread::sleep(Duration::from_secs(1));
+
+                if session::lock_if_idle(&app_handle.state::<CurrentUserState>()) {
+                    let _ = locked_window.emit("locked", ());
+                }
+            });
+
             let plugins_ref = Arc::clone(&app.state::<Arc<Mutex<HashMap<String, Plugin>>>>());
 
             app.listen_global("plugin", move |event| {
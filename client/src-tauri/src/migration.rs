@@ -0,0 +1,525 @@
+use std::path::Path;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use url::Url;
+use zeroize::Zeroizing;
+
+use crate::{
+    error::PassyError,
+    otp::OTP_FIELD,
+    password::{Metadata, Password},
+    secret::SecretString,
+    vault::Vault,
+};
+
+const MIGRATION_PREFIX: &str = "otpauth-migration://";
+
+/// An account of an authenticator app export
+struct OtpAccount {
+    secret: Zeroizing<Vec<u8>>,
+    name: String,
+    issuer: String,
+    algorithm: &'static str,
+    digits: u32,
+    hotp: bool,
+    counter: u64,
+}
+
+fn invalid(reason: &str) -> PassyError {
+    PassyError::InvalidOtpMigration(reason.to_string())
+}
+
+/// Creates one entry per account of an `otpauth-migration://offline?data=` export, under `folder`
+pub fn import_uri(vault: &Vault, uri: &str, folder: &str) -> Result<Vec<Password>, PassyError> {
+    let accounts = decode_uri(uri)?;
+    import_accounts(vault, accounts, folder)
+}
+
+/// Accounts of an `otpauth-migration://offline?data=` export
+fn decode_uri(uri: &str) -> Result<Vec<OtpAccount>, PassyError> {
+    let url = Url::parse(uri.trim()).map_err(|_| invalid("malformed URI"))?;
+    if !uri.trim().starts_with(MIGRATION_PREFIX) || url.host_str() != Some("offline") {
+        return Err(invalid("not an otpauth-migration URI"));
+    }
+
+    let data = url
+        .query_pairs()
+        .find(|(key, _)| key == "data")
+        .map(|(_, data)| Zeroizing::new(data.into_owned()))
+        .ok_or(invalid("missing data"))?;
+
+    // Exports are sometimes written with the URL safe alphabet, and a `+` left unescaped is read
+    // as a space by the query parser
+    let normalized: Zeroizing<String> = Zeroizing::new(
+        data.trim()
+            .chars()
+            .map(|c| match c {
+                '-' | ' ' => '+',
+                '_' => '/',
+                c => c,
+            })
+            .filter(|c| *c != '=')
+            .collect(),
+    );
+    let padded = Zeroizing::new(format!(
+        "{}{}",
+        *normalized,
+        "=".repeat((4 - normalized.len() % 4) % 4)
+    ));
+    let payload = Zeroizing::new(
+        STANDARD
+            .decode(padded.as_bytes())
+            .map_err(|_| invalid("data is not valid base64"))?,
+    );
+
+    parse_payload(&payload)
+}
+
+/// Creates the entries of `accounts`, which are all decoded beforehand so that a malformed export
+/// doesn't leave half of its accounts imported
+fn import_accounts(
+    vault: &Vault,
+    accounts: Vec<OtpAccount>,
+    folder: &str,
+) -> Result<Vec<Password>, PassyError> {
+    let mut passwords = Vec::new();
+    for account in accounts {
+        let path = free_path(vault, folder, &account)?;
+        passwords.push(Password::new(vault, path, account.into_metadata())?);
+    }
+
+    Ok(passwords)
+}
+
+/// Imports every otpauth-migration QR code found in the image at `path`
+pub fn import_image(vault: &Vault, path: &Path, folder: &str) -> Result<Vec<Password>, PassyError> {
+    let image = image::open(path)
+        .map_err(|_| PassyError::FailedToReadQrImage(path.to_owned()))?
+        .to_luma8();
+
+    let mut prepared = rqrr::PreparedImage::prepare(image);
+    let uris: Vec<Zeroizing<String>> = prepared
+        .detect_grids()
+        .iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_, content)| Zeroizing::new(content))
+        .filter(|content| content.starts_with(MIGRATION_PREFIX))
+        .collect();
+
+    if uris.is_empty() {
+        return Err(invalid("no otpauth-migration QR code found in the image"));
+    }
+
+    let mut accounts = Vec::new();
+    for uri in uris {
+        accounts.append(&mut decode_uri(&uri)?);
+    }
+
+    import_accounts(vault, accounts, folder)
+}
+
+impl OtpAccount {
+    /// Metadata of the entry, the settings are kept as an `otpauth://` URI in the otp field
+    fn into_metadata(self) -> Metadata {
+        let kind = if self.hotp { "hotp" } else { "totp" };
+        let label = if self.issuer.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}:{}", self.issuer, self.name)
+        };
+
+        let mut url = Url::parse(&format!("otpauth://{}/", kind)).expect("static URL is valid");
+        url.set_path(&label);
+        {
+            let secret = Zeroizing::new(base32::encode(
+                base32::Alphabet::Rfc4648 { padding: false },
+                &self.secret,
+            ));
+            let mut query = url.query_pairs_mut();
+            query.append_pair("secret", &secret);
+            if !self.issuer.is_empty() {
+                query.append_pair("issuer", &self.issuer);
+            }
+            query
+                .append_pair("algorithm", self.algorithm)
+                .append_pair("digits", &self.digits.to_string());
+            if self.hotp {
+                query.append_pair("counter", &self.counter.to_string());
+            }
+        }
+
+        let mut data = Metadata::default();
+        let fields = data.get_mut();
+        fields.insert(OTP_FIELD.to_string(), SecretString::from(String::from(url)));
+        if !self.issuer.is_empty() {
+            fields.insert("issuer".to_string(), self.issuer.as_str().into());
+        }
+        fields.insert("username".to_string(), self.name.as_str().into());
+
+        data
+    }
+}
+
+/// First path not taken in `folder` for the entry of `account`
+fn free_path(vault: &Vault, folder: &str, account: &OtpAccount) -> Result<String, PassyError> {
+    let name = if account.issuer.is_empty() {
+        account.name.clone()
+    } else {
+        format!("{} ({})", account.issuer, account.name)
+    };
+
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect();
+    let name = if name.trim().is_empty() {
+        "otp".to_string()
+    } else {
+        name.trim().to_string()
+    };
+
+    let folder = folder.trim_matches('/');
+    let base = if folder.is_empty() {
+        name
+    } else {
+        format!("{}/{}", folder, name)
+    };
+
+    let mut path = base.clone();
+    let mut i = 2;
+    while Password::exists(vault, &path)? {
+        path = format!("{} {}", base, i);
+        i += 1;
+    }
+
+    Ok(path)
+}
+
+/// Decodes the `MigrationPayload` protobuf message of Google Authenticator exports
+fn parse_payload(payload: &[u8]) -> Result<Vec<OtpAccount>, PassyError> {
+    let mut accounts = Vec::new();
+
+    for field in ProtoReader::new(payload) {
+        if let (1, ProtoValue::Bytes(params)) = field? {
+            accounts.push(parse_otp_parameters(params)?);
+        }
+    }
+
+    Ok(accounts)
+}
+
+fn parse_otp_parameters(params: &[u8]) -> Result<OtpAccount, PassyError> {
+    let mut account = OtpAccount {
+        secret: Zeroizing::new(Vec::new()),
+        name: String::new(),
+        issuer: String::new(),
+        algorithm: "SHA1",
+        digits: 6,
+        hotp: false,
+        counter: 0,
+    };
+
+    for field in ProtoReader::new(params) {
+        match field? {
+            (1, ProtoValue::Bytes(secret)) => account.secret = Zeroizing::new(secret.to_vec()),
+            (2, ProtoValue::Bytes(name)) => account.name = String::from_utf8_lossy(name).into(),
+            (3, ProtoValue::Bytes(issuer)) => {
+                account.issuer = String::from_utf8_lossy(issuer).into()
+            }
+            (4, ProtoValue::Varint(algorithm)) => {
+                account.algorithm = match algorithm {
+                    0 | 1 => "SHA1",
+                    2 => "SHA256",
+                    3 => "SHA512",
+                    _ => return Err(invalid("unsupported algorithm")),
+                }
+            }
+            (5, ProtoValue::Varint(digits)) => account.digits = if digits == 2 { 8 } else { 6 },
+            (6, ProtoValue::Varint(kind)) => account.hotp = kind == 1,
+            (7, ProtoValue::Varint(counter)) => account.counter = counter,
+            _ => {}
+        }
+    }
+
+    if account.secret.is_empty() {
+        return Err(invalid("an account has no secret"));
+    }
+
+    // Names are often prefixed by their issuer already
+    if let Some(name) = account
+        .name
+        .strip_prefix(&format!("{}:", account.issuer))
+        .filter(|_| !account.issuer.is_empty())
+    {
+        account.name = name.trim().to_string();
+    }
+
+    Ok(account)
+}
+
+enum ProtoValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Iterates over the fields of a protobuf message, as field numbers and values
+struct ProtoReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ProtoReader { data, pos: 0 }
+    }
+
+    fn varint(&mut self) -> Result<u64, PassyError> {
+        let mut value = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or(invalid("truncated payload"))?;
+            self.pos += 1;
+
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(invalid("malformed payload"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PassyError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(invalid("truncated payload"))?;
+
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn field(&mut self) -> Result<(u64, ProtoValue<'a>), PassyError> {
+        let key = self.varint()?;
+
+        let value = match key & 0x7 {
+            0 => ProtoValue::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                ProtoValue::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                ProtoValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                ProtoValue::Fixed
+            }
+            _ => return Err(invalid("unsupported protobuf wire type")),
+        };
+
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for ProtoReader<'a> {
+    type Item = Result<(u64, ProtoValue<'a>), PassyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+
+        let field = self.field();
+        if field.is_err() {
+            // Nothing after a malformed field can be trusted
+            self.pos = self.data.len();
+        }
+
+        Some(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    /// Encodes to base64 as `+/v7` whatever its position, which covers the characters the URL
+    /// safe alphabet replaces
+    const SECRET: [u8; 6] = [0xfb; 6];
+
+    fn varint(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while value >= 0x80 {
+            bytes.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+        bytes
+    }
+
+    fn varint_field(number: u64, value: u64) -> Vec<u8> {
+        let mut field = varint(number << 3);
+        field.extend(varint(value));
+        field
+    }
+
+    fn bytes_field(number: u64, value: &[u8]) -> Vec<u8> {
+        let mut field = varint(number << 3 | 2);
+        field.extend(varint(value.len() as u64));
+        field.extend(value);
+        field
+    }
+
+    fn account(name: &str, issuer: &str, extra: &[Vec<u8>]) -> Vec<u8> {
+        let mut params = bytes_field(1, &SECRET);
+        params.extend(bytes_field(2, name.as_bytes()));
+        params.extend(bytes_field(3, issuer.as_bytes()));
+        for field in extra {
+            params.extend(field);
+        }
+        bytes_field(1, &params)
+    }
+
+    fn uri(data: &str) -> String {
+        format!("{}offline?data={}", MIGRATION_PREFIX, data)
+    }
+
+    fn escaped(data: &str) -> String {
+        url::form_urlencoded::byte_serialize(data.as_bytes()).collect()
+    }
+
+    fn is_invalid<T>(result: Result<T, PassyError>) -> bool {
+        matches!(result, Err(PassyError::InvalidOtpMigration(_)))
+    }
+
+    #[test]
+    fn decodes_standard_base64() {
+        let data = STANDARD.encode(account("alice", "Example", &[]));
+        assert!(data.contains('+') && data.contains('/'));
+
+        let accounts = decode_uri(&uri(&escaped(&data))).unwrap();
+
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(*accounts[0].secret, SECRET);
+        assert_eq!(accounts[0].name, "alice");
+        assert_eq!(accounts[0].issuer, "Example");
+        assert_eq!(accounts[0].algorithm, "SHA1");
+        assert_eq!(accounts[0].digits, 6);
+        assert!(!accounts[0].hotp);
+    }
+
+    #[test]
+    fn decodes_url_safe_base64() {
+        let data = URL_SAFE_NO_PAD.encode(account("alice", "Example", &[]));
+
+        let accounts = decode_uri(&uri(&data)).unwrap();
+
+        assert_eq!(*accounts[0].secret, SECRET);
+    }
+
+    #[test]
+    fn decodes_unescaped_plus() {
+        let data = STANDARD.encode(account("alice", "Example", &[]));
+
+        let accounts = decode_uri(&uri(&data)).unwrap();
+
+        assert_eq!(*accounts[0].secret, SECRET);
+    }
+
+    #[test]
+    fn decodes_every_account() {
+        let mut payload = account("Example:alice", "Example", &[]);
+        payload.extend(account("bob", "", &[]));
+        // Fields of the payload other than the accounts are skipped
+        payload.extend(varint_field(2, 1));
+
+        let accounts = decode_uri(&uri(&escaped(&STANDARD.encode(payload)))).unwrap();
+
+        let names: Vec<(&str, &str)> = accounts
+            .iter()
+            .map(|account| (account.issuer.as_str(), account.name.as_str()))
+            .collect();
+        assert_eq!(names, vec![("Example", "alice"), ("", "bob")]);
+    }
+
+    #[test]
+    fn decodes_hotp_with_counter() {
+        let extra = [
+            varint_field(4, 2),
+            varint_field(5, 2),
+            varint_field(6, 1),
+            varint_field(7, 300),
+        ];
+        let data = STANDARD.encode(account("alice", "Example", &extra));
+
+        let mut accounts = decode_uri(&uri(&escaped(&data))).unwrap();
+        let account = accounts.remove(0);
+
+        assert!(account.hotp);
+        assert_eq!(account.counter, 300);
+        assert_eq!(account.algorithm, "SHA256");
+        assert_eq!(account.digits, 8);
+
+        let metadata = account.into_metadata();
+        let otp = &**metadata.get().get(OTP_FIELD).unwrap();
+        assert!(otp.starts_with("otpauth://hotp/Example:alice?"));
+        assert!(otp.contains("counter=300"));
+    }
+
+    #[test]
+    fn rejects_malformed_uris() {
+        let data = escaped(&STANDARD.encode(account("alice", "Example", &[])));
+
+        assert!(is_invalid(decode_uri("not a uri")));
+        assert!(is_invalid(decode_uri(&format!(
+            "otpauth://offline?data={}",
+            data
+        ))));
+        assert!(is_invalid(decode_uri(&format!(
+            "{}online?data={}",
+            MIGRATION_PREFIX, data
+        ))));
+        assert!(is_invalid(decode_uri(&format!(
+            "{}offline",
+            MIGRATION_PREFIX
+        ))));
+        assert!(is_invalid(decode_uri(&uri("not*base64"))));
+    }
+
+    #[test]
+    fn rejects_malformed_payloads() {
+        let payload = account("alice", "Example", &[]);
+        for len in 1..payload.len() {
+            assert!(is_invalid(parse_payload(&payload[..len])), "length {}", len);
+        }
+
+        // Varint longer than 64 bits
+        assert!(is_invalid(parse_payload(&[
+            0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01
+        ])));
+        // Length past the end of the payload
+        assert!(is_invalid(parse_payload(&[
+            0x0a, 0xff, 0xff, 0xff, 0xff, 0x0f, 0x00
+        ])));
+        // Group wire types aren't supported
+        assert!(is_invalid(parse_payload(&[0x0b])));
+        // Accounts need a secret
+        assert!(is_invalid(parse_payload(&bytes_field(
+            1,
+            &bytes_field(2, b"alice")
+        ))));
+        assert!(is_invalid(parse_otp_parameters(&varint_field(4, 9))));
+    }
+}
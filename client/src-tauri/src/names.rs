@@ -0,0 +1,72 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic,
+    crypto::{open, seal},
+    error::PassyError,
+};
+
+pub const NAMES_INDEX_FILE: &str = "names.index";
+
+/// Maps passwords paths to the opaque file names used on disk by vaults with hidden names
+#[derive(Serialize, Deserialize, Default)]
+pub struct NamesIndex(HashMap<String, String>);
+
+impl NamesIndex {
+    pub fn read(key: &[u8; 32], user_path: &Path) -> Result<Self, PassyError> {
+        let path = user_path.join(NAMES_INDEX_FILE);
+        if !Path::exists(&path) {
+            return Ok(NamesIndex::default());
+        }
+
+        let content = fs::read(&path).map_err(|_| PassyError::FailedToReadIndex(path.clone()))?;
+        NamesIndex::from_sealed(key, &content, &path)
+    }
+
+    /// Deciphers an index read from `path`, which is only used for errors
+    pub fn from_sealed(key: &[u8; 32], content: &[u8], path: &Path) -> Result<Self, PassyError> {
+        let data =
+            open(key, content).map_err(|_| PassyError::FailedToReadIndex(path.to_owned()))?;
+        serde_json::from_slice(&data).map_err(|_| PassyError::FailedToReadIndex(path.to_owned()))
+    }
+
+    pub fn write(&self, key: &[u8; 32], user_path: &Path) -> Result<(), PassyError> {
+        let path = user_path.join(NAMES_INDEX_FILE);
+        let data =
+            serde_json::to_vec(self).map_err(|_| PassyError::FailedToWriteIndex(path.clone()))?;
+        let content = seal(key, &data).map_err(|_| PassyError::FailedToWriteIndex(path.clone()))?;
+        atomic::write(&path, content).map_err(|_| PassyError::FailedToWriteIndex(path))
+    }
+
+    /// Opaque file name of the password at `path`
+    pub fn get(&self, path: &str) -> Option<&String> {
+        self.0.get(path)
+    }
+
+    /// Opaque file name of the password at `path`, a new random one is given if it has none yet
+    pub fn get_or_insert(&mut self, path: &str) -> String {
+        self.0
+            .entry(path.to_string())
+            .or_insert_with(random_name)
+            .clone()
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<String> {
+        self.0.remove(path)
+    }
+
+    /// Passwords paths along with their file names
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+/// Random file name telling nothing about what the file holds
+pub fn random_name() -> String {
+    let mut id = [0; 16];
+    OsRng.fill_bytes(&mut id);
+    id.iter().map(|b| format!("{:02x}", b)).collect()
+}
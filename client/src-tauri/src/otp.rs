@@ -0,0 +1,322 @@
+use hmac::{digest::KeyInit, Hmac, Mac};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use url::Url;
+use zeroize::Zeroizing;
+
+use crate::{
+    error::PassyError,
+    password::{unix_now, Password},
+    secret::{SecretBytes, SecretString},
+    vault::Vault,
+};
+
+/// Field of `Metadata` holding an `otpauth://` URI or a bare base32 TOTP secret
+pub const OTP_FIELD: &str = "otp";
+
+const OTPAUTH_SCHEME: &str = "otpauth://";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OtpKind {
+    /// Time based, RFC 6238
+    Totp { period: u64 },
+    /// Counter based, RFC 4226
+    Hotp { counter: u64 },
+}
+
+/// One-time codes settings of an entry
+pub struct Otp {
+    secret: SecretBytes,
+    pub algorithm: OtpAlgorithm,
+    pub digits: u32,
+    pub kind: OtpKind,
+}
+
+#[derive(Serialize)]
+pub struct OtpCode {
+    pub code: SecretString,
+    /// Seconds before a TOTP code expires
+    pub seconds_remaining: Option<u64>,
+    pub period: Option<u64>,
+    /// Counter a HOTP code was computed with
+    pub counter: Option<u64>,
+}
+
+fn invalid(reason: &str) -> PassyError {
+    PassyError::InvalidOtp(reason.to_string())
+}
+
+impl Otp {
+    /// Parses an `otpauth://` URI, anything else is taken as a base32 TOTP secret with the
+    /// default settings
+    pub fn parse(value: &str) -> Result<Self, PassyError> {
+        let value = value.trim();
+        if !value.starts_with(OTPAUTH_SCHEME) {
+            return Ok(Otp {
+                secret: decode_secret(value)?,
+                algorithm: OtpAlgorithm::Sha1,
+                digits: 6,
+                kind: OtpKind::Totp { period: 30 },
+            });
+        }
+
+        let url = Url::parse(value).map_err(|_| invalid("malformed otpauth URI"))?;
+
+        let mut secret = None;
+        let mut algorithm = OtpAlgorithm::Sha1;
+        let mut digits = 6;
+        let mut period = 30;
+        let mut counter = 0;
+
+        for (key, value) in url.query_pairs() {
+            match key.to_lowercase().as_str() {
+                "secret" => secret = Some(decode_secret(&value)?),
+                "algorithm" => {
+                    algorithm = match value.to_uppercase().as_str() {
+                        "SHA1" => OtpAlgorithm::Sha1,
+                        "SHA256" => OtpAlgorithm::Sha256,
+                        "SHA512" => OtpAlgorithm::Sha512,
+                        _ => return Err(invalid("unsupported algorithm")),
+                    }
+                }
+                "digits" => digits = value.parse().map_err(|_| invalid("invalid digits"))?,
+                "period" => period = value.parse().map_err(|_| invalid("invalid period"))?,
+                "counter" => counter = value.parse().map_err(|_| invalid("invalid counter"))?,
+                _ => {}
+            }
+        }
+
+        if !(1..=10).contains(&digits) {
+            return Err(invalid("digits must be between 1 and 10"));
+        }
+        if period == 0 {
+            return Err(invalid("period can't be 0"));
+        }
+
+        let kind = match url.host_str() {
+            Some("totp") => OtpKind::Totp { period },
+            Some("hotp") => OtpKind::Hotp { counter },
+            _ => return Err(invalid("unknown otpauth type")),
+        };
+
+        Ok(Otp {
+            secret: secret.ok_or(invalid("missing secret"))?,
+            algorithm,
+            digits,
+            kind,
+        })
+    }
+
+    /// HOTP code for `counter`, TOTP codes being HOTP codes of the current time step
+    pub fn code_at(&self, counter: u64) -> SecretString {
+        let message = counter.to_be_bytes();
+        let hash = match self.algorithm {
+            OtpAlgorithm::Sha1 => sign::<Hmac<Sha1>>(&self.secret, &message),
+            OtpAlgorithm::Sha256 => sign::<Hmac<Sha256>>(&self.secret, &message),
+            OtpAlgorithm::Sha512 => sign::<Hmac<Sha512>>(&self.secret, &message),
+        };
+
+        // Dynamic truncation
+        let offset = (hash[hash.len() - 1] & 0xf) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+
+        let code = binary as u64 % 10u64.pow(self.digits);
+        SecretString::from(format!("{:0width$}", code, width = self.digits as usize))
+    }
+
+    /// Code at unix time `now`
+    pub fn code(&self, now: u64) -> OtpCode {
+        match self.kind {
+            OtpKind::Totp { period } => OtpCode {
+                code: self.code_at(now / period),
+                seconds_remaining: Some(period - now % period),
+                period: Some(period),
+                counter: None,
+            },
+            OtpKind::Hotp { counter } => OtpCode {
+                code: self.code_at(counter),
+                seconds_remaining: None,
+                period: None,
+                counter: Some(counter),
+            },
+        }
+    }
+}
+
+fn sign<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> SecretBytes {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message);
+    Zeroizing::new(mac.finalize().into_bytes().to_vec())
+}
+
+fn decode_secret(secret: &str) -> Result<SecretBytes, PassyError> {
+    let secret: Zeroizing<String> = Zeroizing::new(
+        secret
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '=' && *c != '-')
+            .map(|c| c.to_ascii_uppercase())
+            .collect(),
+    );
+
+    base32::decode(base32::Alphabet::Rfc4648 { padding: false }, &secret)
+        .filter(|secret| !secret.is_empty())
+        .map(Zeroizing::new)
+        .ok_or(invalid("secret is not valid base32"))
+}
+
+/// Name of the field holding the entry's one-time codes settings: the `otp` field, or else the
+/// first field holding an `otpauth://` URI
+pub fn find_otp_field(password: &Password) -> Option<String> {
+    let fields = password.data().get();
+    if fields.contains_key(OTP_FIELD) {
+        return Some(OTP_FIELD.to_string());
+    }
+
+    let mut names: Vec<&String> = fields
+        .iter()
+        .filter(|(_, value)| value.starts_with(OTPAUTH_SCHEME))
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+    names.first().map(|name| name.to_string())
+}
+
+/// Computes the current code of the password at `password_path`, HOTP entries get their counter
+/// advanced and saved
+pub fn get_code(vault: &Vault, password_path: &str) -> Result<OtpCode, PassyError> {
+    let mut password = Password::read(vault, password_path)?;
+    let field = find_otp_field(&password).ok_or(PassyError::NoOtp(password_path.to_string()))?;
+    let value = password.data().get()[&field].clone();
+
+    let otp = Otp::parse(&value)?;
+    let code = otp.code(unix_now());
+
+    if let OtpKind::Hotp { counter } = otp.kind {
+        let uri = with_counter(&value, counter + 1)?;
+        password.data_mut().get_mut().insert(field, uri);
+        password.write(vault)?;
+    }
+
+    Ok(code)
+}
+
+/// Sets the counter parameter of an `otpauth://` URI
+fn with_counter(uri: &str, counter: u64) -> Result<SecretString, PassyError> {
+    let mut url = Url::parse(uri).map_err(|_| invalid("malformed otpauth URI"))?;
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key.to_lowercase() != "counter")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("counter", &counter.to_string());
+
+    Ok(SecretString::from(String::from(url)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn otp(secret: &[u8], algorithm: OtpAlgorithm, digits: u32, kind: OtpKind) -> Otp {
+        Otp {
+            secret: Zeroizing::new(secret.to_vec()),
+            algorithm,
+            digits,
+            kind,
+        }
+    }
+
+    /// RFC 4226 appendix D
+    #[test]
+    fn hotp_rfc4226_vectors() {
+        let codes = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+
+        for (counter, expected) in codes.iter().enumerate() {
+            let otp = otp(
+                b"12345678901234567890",
+                OtpAlgorithm::Sha1,
+                6,
+                OtpKind::Hotp {
+                    counter: counter as u64,
+                },
+            );
+            assert_eq!(&*otp.code(0).code, *expected, "counter {}", counter);
+        }
+    }
+
+    /// RFC 6238 appendix B
+    #[test]
+    fn totp_rfc6238_vectors() {
+        let sha1: &[u8] = b"12345678901234567890";
+        let sha256: &[u8] = b"12345678901234567890123456789012";
+        let sha512: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+        let vectors = [
+            (59, ["94287082", "46119246", "90693936"]),
+            (1111111109, ["07081804", "68084774", "25091201"]),
+            (1111111111, ["14050471", "67062674", "99943326"]),
+            (1234567890, ["89005924", "91819424", "93441116"]),
+            (2000000000, ["69279037", "90698825", "38618901"]),
+            (20000000000, ["65353130", "77737706", "47863826"]),
+        ];
+
+        for (time, codes) in vectors {
+            let algorithms = [
+                (sha1, OtpAlgorithm::Sha1),
+                (sha256, OtpAlgorithm::Sha256),
+                (sha512, OtpAlgorithm::Sha512),
+            ];
+
+            for ((secret, algorithm), expected) in algorithms.into_iter().zip(codes) {
+                let otp = otp(secret, algorithm, 8, OtpKind::Totp { period: 30 });
+                assert_eq!(
+                    &*otp.code(time).code,
+                    expected,
+                    "{:?} at {}",
+                    algorithm,
+                    time
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parses_otpauth_uri() {
+        let otp = Otp::parse(
+            "otpauth://totp/Example:alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&digits=8&period=30",
+        )
+        .unwrap();
+
+        assert_eq!(otp.algorithm, OtpAlgorithm::Sha1);
+        assert_eq!(otp.kind, OtpKind::Totp { period: 30 });
+        assert_eq!(&*otp.code(59).code, "94287082");
+    }
+
+    #[test]
+    fn hotp_counter_is_advanced_in_uri() {
+        let uri = with_counter("otpauth://hotp/x?secret=GEZDGNBV&counter=4", 5).unwrap();
+        let otp = Otp::parse(&uri).unwrap();
+        assert_eq!(otp.kind, OtpKind::Hotp { counter: 5 });
+    }
+}
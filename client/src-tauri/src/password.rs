@@ -1,18 +1,27 @@
 use crate::{
-    crypto::{cipher, decipher},
+    atomic,
+    crypto::CipherSuite,
     error::{PasswordReadFailedReason, PasswordWriteFailedReason},
+    header::{Header, CURRENT_VERSION},
+    names::NamesIndex,
+    revisions::history_file,
+    secret::SecretString,
+    strength::{self, Strength},
+    vault::Vault,
 };
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    fs,
+    fs::{self, File},
+    io::Read,
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::error::PassyError;
 
-#[derive(Serialize, Default, Deserialize)]
-pub struct Metadata(HashMap<String, String>);
+#[derive(Serialize, Default, Deserialize, Clone)]
+pub struct Metadata(HashMap<String, SecretString>);
 impl Metadata {
     pub fn parse(data: &str) -> Result<Metadata, ()> {
         let mut map = HashMap::new();
@@ -20,7 +29,7 @@ impl Metadata {
         for line in data.lines() {
             let mut parts = line.splitn(2, ':');
             let key = parts.next().ok_or(())?.trim().to_string();
-            let value = parts.next().ok_or(())?.trim().to_string();
+            let value = SecretString::from(parts.next().ok_or(())?.trim());
 
             map.insert(key, value);
         }
@@ -28,48 +37,128 @@ impl Metadata {
         Ok(Metadata(map))
     }
 
-    pub fn stringify(&self) -> String {
-        self.get()
-            .iter()
-            .map(|(k, v)| format!("{}:{}", k, v))
-            .collect::<Vec<_>>()
-            .join("\n")
+    pub fn stringify(&self) -> SecretString {
+        // Sized upfront so that no partial copy of the data is left behind by reallocations
+        let len = self.get().iter().map(|(k, v)| k.len() + v.len() + 2).sum();
+        let mut data = String::with_capacity(len);
+
+        for (i, (k, v)) in self.get().iter().enumerate() {
+            if i > 0 {
+                data.push('\n');
+            }
+            data.push_str(k);
+            data.push(':');
+            data.push_str(v);
+        }
+
+        SecretString::from(data)
     }
 
-    pub fn get(&self) -> &HashMap<String, String> {
+    pub fn get(&self) -> &HashMap<String, SecretString> {
         &self.0
     }
 
-    pub fn get_mut(&mut self) -> &mut HashMap<String, String> {
+    pub fn get_mut(&mut self) -> &mut HashMap<String, SecretString> {
         &mut self.0
     }
 }
 
+/// Field of `Metadata` holding the password itself
+pub const PASSWORD_FIELD: &str = "password";
+/// Field saved along with the metadata for `Password::password_changed`, hidden from `data`
+const PASSWORD_CHANGED_FIELD: &str = "__password_changed";
+
 #[derive(Serialize, Deserialize)]
 pub struct Password {
     pub path: String,
     data: Metadata,
+    /// Unix time at which the password field last changed
+    #[serde(default)]
+    pub password_changed: Option<u64>,
+    /// Estimated strength of the password field, computed by the backend only
+    #[serde(default, skip_deserializing)]
+    pub strength: Option<Strength>,
 }
 
 impl Password {
-    pub fn new(
-        key: &[u8; 32],
-        appdata: &Path,
-        username: &str,
-        path: String,
-        data: Metadata,
-    ) -> Result<Self, PassyError> {
-        let pwd = Password { path, data };
-        pwd.write(key, appdata, username)?;
+    pub fn new(vault: &Vault, path: String, data: Metadata) -> Result<Self, PassyError> {
+        let mut pwd = Password {
+            path,
+            data,
+            password_changed: None,
+            strength: None,
+        };
+        pwd.track_password_change(None);
+        pwd.write(vault)?;
+        pwd.update_strength();
         Ok(pwd)
     }
 
-    pub fn write(&self, key: &[u8; 32], appdata: &Path, username: &str) -> Result<(), PassyError> {
-        let path = Password::get_path(appdata, username, &(self.path.to_string() + ".passy"));
-        let data = Metadata::stringify(&self.data);
-        let (mut content, nonce) = cipher(key, data.as_bytes()).map_err(|_| {
-            PassyError::FailedToWritePassword(path.clone(), PasswordWriteFailedReason::CipherError)
-        })?;
+    /// Keeps the date the password field last changed up to date, `previous` being the password
+    /// as it currently is on disk
+    pub fn track_password_change(&mut self, previous: Option<&Password>) {
+        self.password_changed = match previous {
+            Some(previous) if previous.password() == self.password() => previous.password_changed,
+            _ => self.password().map(|_| unix_now()),
+        };
+    }
+
+    pub fn data(&self) -> &Metadata {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut Metadata {
+        &mut self.data
+    }
+
+    /// Value of the password field, if any
+    pub fn password(&self) -> Option<&SecretString> {
+        self.data.get().get(PASSWORD_FIELD)
+    }
+
+    /// Estimates again the strength of the password field
+    pub fn update_strength(&mut self) {
+        self.strength = self.password().map(|password| strength::estimate(password));
+    }
+
+    pub fn write(&self, vault: &Vault) -> Result<(), PassyError> {
+        if vault.config.hidden_names {
+            let mut index = NamesIndex::read(&vault.key, &vault.path)?;
+            let known = index.get(&self.path).is_some();
+            let file = index.get_or_insert(&self.path);
+
+            self.write_to(vault, &vault.path.join(file + ".passy"))?;
+            if !known {
+                index.write(&vault.key, &vault.path)?;
+            }
+
+            Ok(())
+        } else {
+            self.write_to(vault, &vault.path.join(self.path.to_string() + ".passy"))
+        }
+    }
+
+    /// Writes the password at `path` on disk, it stays bound to its own path for the vault user
+    pub fn write_to(&self, vault: &Vault, path: &Path) -> Result<(), PassyError> {
+        let cipher = vault.config.cipher;
+        let mut metadata = Metadata(self.data.0.clone());
+        if let Some(changed) = self.password_changed {
+            metadata.get_mut().insert(
+                PASSWORD_CHANGED_FIELD.to_string(),
+                changed.to_string().into(),
+            );
+        }
+        let data = metadata.stringify();
+        let aad = associated_data(&vault.username, &self.path);
+        let (mut content, nonce) =
+            cipher
+                .cipher(&vault.key, data.as_bytes(), &aad)
+                .map_err(|_| {
+                    PassyError::FailedToWritePassword(
+                        path.to_owned(),
+                        PasswordWriteFailedReason::CipherError,
+                    )
+                })?;
 
         if let Some(parent_dir) = path.parent() {
             fs::create_dir_all(parent_dir).map_err(|_| {
@@ -77,85 +166,281 @@ impl Password {
             })?;
         }
 
-        let mut data = nonce.to_vec();
+        let mut data = Header::current(cipher).to_bytes();
+        data.extend(nonce);
         data.append(&mut content);
 
-        fs::write(&path, data).map_err(|_| {
-            PassyError::FailedToWritePassword(path, PasswordWriteFailedReason::WritePermission)
+        atomic::write(path, data).map_err(|_| {
+            PassyError::FailedToWritePassword(
+                path.to_owned(),
+                PasswordWriteFailedReason::WritePermission,
+            )
         })
     }
 
-    pub fn get_pwdpath_from_ospath(user_path: &Path, path: &Path) -> String {
-        let str1 = &path
-            .to_string_lossy()
-            .to_string()
-            .replace(user_path.to_string_lossy().to_string().as_str(), "")[1..];
+    pub fn read(vault: &Vault, path: &str) -> Result<Self, PassyError> {
+        let file = Password::get_path(vault, path)?.ok_or(PassyError::FailedToReadPassword(
+            PathBuf::from(path),
+            PasswordReadFailedReason::FileNotFound,
+        ))?;
 
-        str1[..str1.len() - 6].to_string()
+        Password::read_file(vault, &file, path.to_string())
     }
-    pub fn read(key: &[u8; 32], user_path: &Path, path: &str) -> Result<Self, PassyError> {
-        let path = user_path.join(path);
 
-        let content = fs::read(&path).map_err(|_| {
+    /// Reads the password at `path` from its `file` on disk
+    pub fn read_file(vault: &Vault, file: &Path, path: String) -> Result<Self, PassyError> {
+        let content = fs::read(file).map_err(|_| {
             PassyError::FailedToReadPassword(
-                path.clone().into(),
+                file.to_owned(),
                 PasswordReadFailedReason::FileNotFound,
             )
         })?;
 
-        let nonce: [u8; 12] = content[..12].try_into().map_err(|_| {
-            PassyError::FailedToReadPassword(
-                path.clone(),
+        Password::from_content(vault, file, &content, path)
+    }
+
+    /// Deciphers the `content` of a password file, `file` is only used for errors
+    pub fn from_content(
+        vault: &Vault,
+        file: &Path,
+        content: &[u8],
+        path: String,
+    ) -> Result<Self, PassyError> {
+        let header = Header::parse(content)
+            .map_err(|reason| PassyError::FailedToReadPassword(file.to_owned(), reason))?;
+
+        // Legacy files have no header and always use AES-256-GCM
+        let (body, cipher) = match header {
+            Some(header) => (&content[Header::LEN..], header.cipher),
+            None => (content, CipherSuite::Aes256Gcm),
+        };
+
+        if body.len() < cipher.nonce_len() {
+            return Err(PassyError::FailedToReadPassword(
+                file.to_owned(),
                 PasswordReadFailedReason::MalformedMetadata,
-            )
-        })?;
+            ));
+        }
 
-        let data = &content[12..];
+        let (nonce, data) = body.split_at(cipher.nonce_len());
 
-        let data = decipher(key, nonce, data).map_err(|_| {
-            PassyError::FailedToReadPassword(path.clone(), PasswordReadFailedReason::DecipherError)
-        })?;
+        let binds_path = header.map(|h| h.binds_path()).unwrap_or(false);
+        let aad = if binds_path {
+            associated_data(&vault.username, &path)
+        } else {
+            Vec::new()
+        };
+
+        let data = cipher
+            .decipher(&vault.key, nonce, data, &aad)
+            .map_err(|_| {
+                // The key was checked at login, so a bound file failing was moved or tampered with
+                let reason = if binds_path {
+                    PasswordReadFailedReason::PathMismatch
+                } else {
+                    PasswordReadFailedReason::DecipherError
+                };
+                PassyError::FailedToReadPassword(file.to_owned(), reason)
+            })?;
 
         let text = std::str::from_utf8(&data).map_err(|_| {
             PassyError::FailedToReadPassword(
-                path.clone(),
+                file.to_owned(),
                 PasswordReadFailedReason::MalformedMetadata,
             )
         })?;
 
-        let map = Metadata::parse(text).map_err(|_| {
+        let mut map = Metadata::parse(text).map_err(|_| {
             PassyError::FailedToReadPassword(
-                path.clone(),
+                file.to_owned(),
                 PasswordReadFailedReason::MalformedMetadata,
             )
         })?;
 
+        let password_changed = map
+            .get_mut()
+            .remove(PASSWORD_CHANGED_FIELD)
+            .and_then(|changed| changed.parse().ok());
+
         Ok(Password {
-            path: Password::get_pwdpath_from_ospath(user_path, &path),
+            path,
             data: map,
+            password_changed,
+            strength: None,
+        })
+    }
+
+    /// Path on disk of the file holding the password at `path`, `None` if a vault with hidden
+    /// names has no such password
+    pub fn get_path(vault: &Vault, path: &str) -> Result<Option<PathBuf>, PassyError> {
+        if vault.config.hidden_names {
+            let index = NamesIndex::read(&vault.key, &vault.path)?;
+            Ok(index
+                .get(path)
+                .map(|file| vault.path.join(file.to_string() + ".passy")))
+        } else {
+            Ok(Some(vault.path.join(path.to_string() + ".passy")))
+        }
+    }
+
+    pub fn exists(vault: &Vault, path: &str) -> Result<bool, PassyError> {
+        Ok(Password::get_path(vault, path)?
+            .map(|file| Path::exists(&file))
+            .unwrap_or(false))
+    }
+
+    /// Path on disk of the file holding the password at `path`, vaults with hidden names give a
+    /// new file name to passwords they don't have yet
+    pub fn get_or_create_path(vault: &Vault, path: &str) -> Result<PathBuf, PassyError> {
+        if !vault.config.hidden_names {
+            return Ok(vault.path.join(path.to_string() + ".passy"));
+        }
+
+        let mut index = NamesIndex::read(&vault.key, &vault.path)?;
+        let known = index.get(path).is_some();
+        let file = index.get_or_insert(path);
+        if !known {
+            index.write(&vault.key, &vault.path)?;
+        }
+
+        Ok(vault.path.join(file + ".passy"))
+    }
+
+    /// Deletes the password and its history for good, see `trash::move_to_trash` to keep them
+    pub fn delete(vault: &Vault, password_path: String) -> Result<(), PassyError> {
+        Password::remove_with(vault, &password_path, |file| {
+            fs::remove_file(file)?;
+
+            let history = history_file(file);
+            if Path::exists(&history) {
+                fs::remove_file(history)?;
+            }
+            Ok(())
         })
     }
 
-    pub fn get_path(appdata: &Path, username: &str, path: &str) -> PathBuf {
-        appdata.join(username).join(path)
+    /// Takes the file of the password at `path` out of the vault with `remove`, which gets its
+    /// path on disk. Folders left empty are removed as well.
+    pub fn remove_with(
+        vault: &Vault,
+        path: &str,
+        remove: impl FnOnce(&Path) -> std::io::Result<()>,
+    ) -> Result<(), PassyError> {
+        let file = Password::get_path(vault, path)?
+            .ok_or(PassyError::DeletationFailed(PathBuf::from(path)))?;
+        remove(&file).map_err(|_| PassyError::DeletationFailed(file.clone()))?;
+
+        if vault.config.hidden_names {
+            let mut index = NamesIndex::read(&vault.key, &vault.path)?;
+            index.remove(path);
+            index.write(&vault.key, &vault.path)?;
+        }
+
+        remove_empty_parents(&vault.path, &file)
+    }
+}
+
+/// Removes the folders left empty above `file`, up to the user directory
+fn remove_empty_parents(user_path: &Path, file: &Path) -> Result<(), PassyError> {
+    for dir in file.ancestors().skip(1) {
+        if dir == user_path || !dir.starts_with(user_path) {
+            break;
+        }
+
+        if !fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_none()) {
+            break;
+        }
+
+        fs::remove_dir(dir).map_err(|_| PassyError::DeletationFailed(dir.to_owned()))?;
+    }
+
+    Ok(())
+}
+
+/// Lists the paths of all `.passy` files under `path`, relative to the user directory. Hidden
+/// folders, such as the trash, are left out.
+pub fn list_passy_files(user_path: &Path, path: String) -> Result<Vec<String>, PassyError> {
+    let curr_path = user_path.join(path.clone());
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(&curr_path)
+        .map_err(|_| PassyError::FailedToReadDir(curr_path.to_owned()))?
+        .flatten()
+    {
+        let lossy_filename = entry.file_name().to_string_lossy().to_string();
+
+        if entry.path().is_dir() && !lossy_filename.starts_with('.') {
+            files.append(&mut list_passy_files(
+                user_path,
+                path.clone() + &lossy_filename + "/",
+            )?);
+        } else if lossy_filename.ends_with(".passy") {
+            files.push(path.clone() + &lossy_filename);
+        }
     }
 
-    pub fn delete(password_path: String, username: &str, appdata: &Path) -> Result<(), PassyError> {
-        let path = appdata.join(username).join(password_path + ".passy");
-        fs::remove_file(&path).map_err(|_| PassyError::DeletationFailed(path.clone()))?;
+    Ok(files)
+}
 
-        // let parent_dir = path.parent().unwrap();
+/// Lists every password of the vault as its path along with the file holding it on disk
+pub fn list_entries(vault: &Vault) -> Result<Vec<(String, PathBuf)>, PassyError> {
+    if vault.config.hidden_names {
+        let index = NamesIndex::read(&vault.key, &vault.path)?;
+        return Ok(index
+            .entries()
+            .map(|(path, file)| (path.clone(), vault.path.join(file.to_string() + ".passy")))
+            .collect());
+    }
 
-        // let entries = fs::read_dir(parent_dir)
-        //     .ok()
-        //     .and_then(|entires| Some(entires.count()))
-        //     .unwrap_or(1);
+    Ok(list_passy_files(&vault.path, "".to_string())?
+        .into_iter()
+        .map(|file| {
+            let path = file[..file.len() - ".passy".len()].to_string();
+            (path, vault.path.join(file))
+        })
+        .collect())
+}
 
-        // if entries == 0 {
-        //     fs::remove_dir(parent_dir)
-        //         .map_err(|_| PassyError::DeletationFailed(parent_dir.to_owned()))?;
-        // }
+pub fn recursive_pwd_read(vault: &Vault) -> Result<Vec<Password>, PassyError> {
+    list_entries(vault)?
+        .into_iter()
+        .map(|(path, file)| Password::read_file(vault, &file, path))
+        .collect()
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Associated data binding a password file to its owner and its path in the vault
+pub fn associated_data(username: &str, path: &str) -> Vec<u8> {
+    format!("{}/{}", username, path.replace('\\', "/")).into_bytes()
+}
 
-        Ok(())
+/// Rewrites in place the passwords files written with an older file format version
+pub fn migrate_legacy_files(vault: &Vault) -> Result<(), PassyError> {
+    for (path, file) in list_entries(vault)? {
+        // This runs at each login, only the header tells whether a file needs migrating
+        let mut content = Vec::with_capacity(Header::LEN);
+        File::open(&file)
+            .and_then(|f| f.take(Header::LEN as u64).read_to_end(&mut content))
+            .map_err(|_| {
+                PassyError::FailedToReadPassword(
+                    file.clone(),
+                    PasswordReadFailedReason::FileNotFound,
+                )
+            })?;
+
+        let header = Header::parse(&content)
+            .map_err(|reason| PassyError::FailedToReadPassword(file.clone(), reason))?;
+
+        if header.map(|h| h.version) != Some(CURRENT_VERSION) {
+            Password::read_file(vault, &file, path)?.write_to(vault, &file)?;
+        }
     }
+
+    Ok(())
 }
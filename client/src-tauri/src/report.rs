@@ -0,0 +1,171 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::PassyError,
+    password::{recursive_pwd_read, unix_now, Password},
+    secret::SecretString,
+    strength,
+    vault::Vault,
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Thresholds of the security report
+#[derive(Deserialize)]
+pub struct ReportOptions {
+    /// Passwords scoring below this strength score are weak
+    #[serde(default = "default_min_score")]
+    pub min_score: u8,
+    /// Passwords unchanged for more days than this are old
+    #[serde(default = "default_max_age_days")]
+    pub max_age_days: u64,
+}
+
+fn default_min_score() -> u8 {
+    3
+}
+
+fn default_max_age_days() -> u64 {
+    365
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            min_score: default_min_score(),
+            max_age_days: default_max_age_days(),
+        }
+    }
+}
+
+/// Findings about the passwords of a folder, no password is ever part of it
+#[derive(Serialize, Default)]
+pub struct FolderReport {
+    pub reused: Vec<ReusedPassword>,
+    pub weak: Vec<WeakPassword>,
+    pub old: Vec<OldPassword>,
+    /// Entries without a password field, or with an empty one
+    pub empty: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReusedPassword {
+    pub path: String,
+    /// Other entries using the same password
+    pub shared_with: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct WeakPassword {
+    pub path: String,
+    pub score: u8,
+}
+
+#[derive(Serialize)]
+pub struct OldPassword {
+    pub path: String,
+    pub days: u64,
+}
+
+/// Security findings of the whole vault, by folder
+pub type SecurityReport = BTreeMap<String, FolderReport>;
+
+pub fn security_report(
+    vault: &Vault,
+    options: &ReportOptions,
+) -> Result<SecurityReport, PassyError> {
+    let mut passwords = recursive_pwd_read(vault)?;
+    passwords.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut report = SecurityReport::new();
+    let mut by_value: HashMap<&SecretString, Vec<&str>> = HashMap::new();
+    let now = unix_now();
+
+    for password in &passwords {
+        let folder = report.entry(folder_of(&password.path)).or_default();
+
+        let value = match password.password() {
+            Some(value) if !value.is_empty() => value,
+            _ => {
+                folder.empty.push(password.path.clone());
+                continue;
+            }
+        };
+
+        by_value.entry(value).or_default().push(&password.path);
+
+        let score = strength::estimate(value).score;
+        if score < options.min_score {
+            folder.weak.push(WeakPassword {
+                path: password.path.clone(),
+                score,
+            });
+        }
+
+        if let Some(changed) = last_change(vault, password) {
+            let days = now.saturating_sub(changed) / DAY;
+            if days > options.max_age_days {
+                folder.old.push(OldPassword {
+                    path: password.path.clone(),
+                    days,
+                });
+            }
+        }
+    }
+
+    for paths in by_value.values().filter(|paths| paths.len() > 1) {
+        for path in paths {
+            report
+                .entry(folder_of(path))
+                .or_default()
+                .reused
+                .push(ReusedPassword {
+                    path: path.to_string(),
+                    shared_with: paths
+                        .iter()
+                        .filter(|other| *other != path)
+                        .map(|other| other.to_string())
+                        .collect(),
+                });
+        }
+    }
+
+    report.values_mut().for_each(|folder| {
+        folder.reused.sort_by(|a, b| a.path.cmp(&b.path));
+    });
+    report.retain(|_, folder| {
+        !(folder.reused.is_empty()
+            && folder.weak.is_empty()
+            && folder.old.is_empty()
+            && folder.empty.is_empty())
+    });
+
+    Ok(report)
+}
+
+/// Folder of the password at `path`, empty for the root of the vault
+fn folder_of(path: &str) -> String {
+    path.replace('\\', "/")
+        .rsplit_once('/')
+        .map(|(folder, _)| folder.to_string())
+        .unwrap_or_default()
+}
+
+/// Unix time of the last change of the password field, passwords saved before it was tracked
+/// fall back to the last modification of their file
+fn last_change(vault: &Vault, password: &Password) -> Option<u64> {
+    password.password_changed.or_else(|| {
+        let file = Password::get_path(vault, &password.path).ok()??;
+        let modified = fs::metadata(file).ok()?.modified().ok()?;
+        modified
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs())
+    })
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    atomic,
+    error::PassyError,
+    header::Header,
+    password::{associated_data, unix_now, Metadata, Password},
+    secret::SecretString,
+    vault::Vault,
+};
+
+/// Revisions kept for each password, unless configured otherwise
+pub const DEFAULT_HISTORY_DEPTH: usize = 20;
+
+/// A previous version of a password
+#[derive(Serialize, Deserialize)]
+struct Revision {
+    id: u64,
+    /// Unix time at which this version was replaced
+    saved_at: u64,
+    data: Metadata,
+    password_changed: Option<u64>,
+}
+
+/// Revisions of a password, oldest first, ciphered in a file next to the password file
+#[derive(Serialize, Deserialize, Default)]
+struct RevisionRecord {
+    next_id: u64,
+    revisions: Vec<Revision>,
+}
+
+#[derive(Serialize)]
+pub struct RevisionInfo {
+    pub id: u64,
+    pub saved_at: u64,
+    /// Names of the fields of this version
+    pub fields: Vec<String>,
+}
+
+/// A field that differs between two versions, `None` when a version doesn't have it
+#[derive(Serialize)]
+pub struct FieldDiff {
+    pub field: String,
+    pub before: Option<SecretString>,
+    pub after: Option<SecretString>,
+}
+
+/// File holding the revisions of the password stored in `file`
+pub fn history_file(file: &Path) -> PathBuf {
+    file.with_extension("history")
+}
+
+/// Revisions are bound to their password like the password file itself, but can't pass for it
+fn history_associated_data(vault: &Vault, path: &str) -> Vec<u8> {
+    let mut aad = associated_data(&vault.username, path);
+    aad.extend_from_slice(b"#history");
+    aad
+}
+
+impl RevisionRecord {
+    fn read(vault: &Vault, path: &str) -> Result<Self, PassyError> {
+        match Password::get_path(vault, path)? {
+            Some(file) => RevisionRecord::read_file(vault, path, &file),
+            None => Ok(RevisionRecord::default()),
+        }
+    }
+
+    /// Reads the record of the password at `path`, kept next to its `password_file`
+    fn read_file(vault: &Vault, path: &str, password_file: &Path) -> Result<Self, PassyError> {
+        let file = history_file(password_file);
+        if !Path::exists(&file) {
+            return Ok(RevisionRecord::default());
+        }
+
+        let content = fs::read(&file).map_err(|_| PassyError::FailedToReadHistory(file.clone()))?;
+        RevisionRecord::from_content(vault, path, &file, &content)
+    }
+
+    /// Deciphers the record of the password at `path` from `content`, the content of `file`
+    fn from_content(
+        vault: &Vault,
+        path: &str,
+        file: &Path,
+        content: &[u8],
+    ) -> Result<Self, PassyError> {
+        let header = Header::parse(content)
+            .ok()
+            .flatten()
+            .ok_or(PassyError::FailedToReadHistory(file.to_owned()))?;
+
+        let body = &content[Header::LEN..];
+        if body.len() < header.cipher.nonce_len() {
+            return Err(PassyError::FailedToReadHistory(file.to_owned()));
+        }
+        let (nonce, data) = body.split_at(header.cipher.nonce_len());
+
+        let data = header
+            .cipher
+            .decipher(
+                &vault.key,
+                nonce,
+                data,
+                &history_associated_data(vault, path),
+            )
+            .map_err(|_| PassyError::FailedToReadHistory(file.to_owned()))?;
+
+        serde_json::from_slice(&data).map_err(|_| PassyError::FailedToReadHistory(file.to_owned()))
+    }
+
+    /// Writes the record next to the password file, which must exist. Empty records have no file.
+    fn write(&self, vault: &Vault, path: &str) -> Result<(), PassyError> {
+        let password_file = Password::get_path(vault, path)?
+            .ok_or(PassyError::FailedToWriteHistory(PathBuf::from(path)))?;
+        self.write_file(vault, path, &password_file)
+    }
+
+    /// Writes the record of the password at `path` next to its `password_file`
+    fn write_file(
+        &self,
+        vault: &Vault,
+        path: &str,
+        password_file: &Path,
+    ) -> Result<(), PassyError> {
+        let file = history_file(password_file);
+
+        if self.revisions.is_empty() {
+            if Path::exists(&file) {
+                fs::remove_file(&file).map_err(|_| PassyError::DeletationFailed(file))?;
+            }
+            return Ok(());
+        }
+
+        let data = Zeroizing::new(
+            serde_json::to_vec(self).map_err(|_| PassyError::FailedToWriteHistory(file.clone()))?,
+        );
+        let cipher = vault.config.cipher;
+        let (mut content, nonce) = cipher
+            .cipher(&vault.key, &data, &history_associated_data(vault, path))
+            .map_err(|_| PassyError::FailedToWriteHistory(file.clone()))?;
+
+        let mut bytes = Header::current(cipher).to_bytes();
+        bytes.extend(nonce);
+        bytes.append(&mut content);
+
+        atomic::write(&file, bytes).map_err(|_| PassyError::FailedToWriteHistory(file))
+    }
+
+    /// Appends `password` as the latest revision, the oldest ones are dropped past `depth`
+    fn push(&mut self, mut password: Password, depth: usize) {
+        if depth > 0 {
+            self.revisions.push(Revision {
+                id: self.next_id,
+                saved_at: unix_now(),
+                data: std::mem::take(password.data_mut()),
+                password_changed: password.password_changed,
+            });
+            self.next_id += 1;
+        }
+
+        let excess = self.revisions.len().saturating_sub(depth);
+        self.revisions.drain(..excess);
+    }
+
+    /// Adds the revisions of `other`, another version of this record, that this one doesn't have.
+    /// Revisions got the same ids on both sides until their histories diverged, those saved
+    /// since get new ids when theirs are taken.
+    fn union(&mut self, other: RevisionRecord, depth: usize) {
+        self.next_id = self.next_id.max(other.next_id);
+
+        for mut revision in other.revisions {
+            let known = self.revisions.iter().any(|known| {
+                known.saved_at == revision.saved_at && known.data.get() == revision.data.get()
+            });
+            if known {
+                continue;
+            }
+
+            if self.revisions.iter().any(|known| known.id == revision.id) {
+                revision.id = self.next_id;
+                self.next_id += 1;
+            }
+            self.revisions.push(revision);
+        }
+
+        self.revisions.sort_by_key(|revision| revision.saved_at);
+        let excess = self.revisions.len().saturating_sub(depth);
+        self.revisions.drain(..excess);
+    }
+
+    fn find(&self, path: &str, id: u64) -> Result<&Revision, PassyError> {
+        self.revisions
+            .iter()
+            .find(|revision| revision.id == id)
+            .ok_or(PassyError::RevisionNotFound(path.to_string(), id))
+    }
+}
+
+/// Keeps `previous`, the version of a password about to be replaced, in its history
+pub fn record(vault: &Vault, previous: Password) -> Result<(), PassyError> {
+    let path = previous.path.clone();
+    let mut record = RevisionRecord::read(vault, &path)?;
+    record.push(previous, vault.config.history_depth);
+    record.write(vault, &path)
+}
+
+/// Revisions of the password at `path`, newest first
+pub fn list(vault: &Vault, path: &str) -> Result<Vec<RevisionInfo>, PassyError> {
+    Ok(RevisionRecord::read(vault, path)?
+        .revisions
+        .iter()
+        .rev()
+        .map(|revision| {
+            let mut fields: Vec<String> = revision.data.get().keys().cloned().collect();
+            fields.sort();
+            RevisionInfo {
+                id: revision.id,
+                saved_at: revision.saved_at,
+                fields,
+            }
+        })
+        .collect())
+}
+
+/// Fields that differ from revision `from` to revision `to`, or to the current version if `to`
+/// is `None`
+pub fn diff(
+    vault: &Vault,
+    path: &str,
+    from: u64,
+    to: Option<u64>,
+) -> Result<Vec<FieldDiff>, PassyError> {
+    let record = RevisionRecord::read(vault, path)?;
+    let before = record.find(path, from)?.data.get();
+
+    let current;
+    let after = match to {
+        Some(to) => record.find(path, to)?.data.get(),
+        None => {
+            current = Password::read(vault, path)?;
+            current.data().get()
+        }
+    };
+
+    let fields: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    Ok(fields
+        .into_iter()
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| FieldDiff {
+            field: field.clone(),
+            before: before.get(field).cloned(),
+            after: after.get(field).cloned(),
+        })
+        .collect())
+}
+
+/// Puts back revision `id` of the password at `path`, the current version joins the history
+pub fn restore(vault: &Vault, path: &str, id: u64) -> Result<Password, PassyError> {
+    let mut record = RevisionRecord::read(vault, path)?;
+    let data = record.find(path, id)?.data.clone();
+
+    let current = Password::read(vault, path)?;
+    let mut password = Password::read(vault, path)?;
+    *password.data_mut() = data;
+    password.track_password_change(Some(&current));
+
+    record.push(current, vault.config.history_depth);
+    record.write(vault, path)?;
+    password.write(vault)?;
+
+    password.update_strength();
+    Ok(password)
+}
+
+/// Copies the history of the password at `from_path` in `from` to `to_path` in `to`, where the
+/// password must be written already
+pub fn copy(from: &Vault, to: &Vault, from_path: &str, to_path: &str) -> Result<(), PassyError> {
+    let record = RevisionRecord::read(from, from_path)?;
+    record.write(to, to_path)
+}
+
+/// Adds to the history of the password at `path` the revisions it doesn't have from `content`,
+/// the history `file` of the password in another version of the vault, e.g. on another device
+pub fn merge(vault: &Vault, path: &str, file: &Path, content: &[u8]) -> Result<(), PassyError> {
+    let other = RevisionRecord::from_content(vault, path, file, content)?;
+    let mut record = RevisionRecord::read(vault, path)?;
+    record.union(other, vault.config.history_depth);
+    record.write(vault, path)
+}
+
+/// Same as `merge` with the history of the password at `path` kept next to `from_file`, added
+/// to the one next to `to_file`
+pub fn merge_file(
+    vault: &Vault,
+    path: &str,
+    from_file: &Path,
+    to_file: &Path,
+) -> Result<(), PassyError> {
+    let other = RevisionRecord::read_file(vault, path, from_file)?;
+    let mut record = RevisionRecord::read_file(vault, path, to_file)?;
+    record.union(other, vault.config.history_depth);
+    record.write_file(vault, path, to_file)
+}
+
+/// Same as `copy`, for callers that already know the files of both passwords, which spares
+/// reading the names index of vaults with hidden names
+pub fn copy_file(
+    from: &Vault,
+    to: &Vault,
+    path: &str,
+    from_file: &Path,
+    to_file: &Path,
+) -> Result<(), PassyError> {
+    let record = RevisionRecord::read_file(from, path, from_file)?;
+    record.write_file(to, path, to_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revision(id: u64, saved_at: u64, password: &str) -> Revision {
+        Revision {
+            id,
+            saved_at,
+            data: Metadata::parse(&format!("password:{}", password)).unwrap(),
+            password_changed: None,
+        }
+    }
+
+    fn record(revisions: Vec<Revision>) -> RevisionRecord {
+        RevisionRecord {
+            next_id: revisions.iter().map(|r| r.id + 1).max().unwrap_or(0),
+            revisions,
+        }
+    }
+
+    fn summary(record: &RevisionRecord) -> Vec<(u64, u64)> {
+        record
+            .revisions
+            .iter()
+            .map(|revision| (revision.id, revision.saved_at))
+            .collect()
+    }
+
+    #[test]
+    fn union_keeps_the_revisions_of_both_sides() {
+        let mut ours = record(vec![revision(0, 10, "a"), revision(1, 20, "b")]);
+        let theirs = record(vec![revision(0, 10, "a"), revision(1, 15, "c")]);
+
+        ours.union(theirs, DEFAULT_HISTORY_DEPTH);
+
+        // The shared revision is kept once, the one saved on the other side gets a new id
+        assert_eq!(summary(&ours), vec![(0, 10), (2, 15), (1, 20)]);
+        assert_eq!(ours.next_id, 3);
+
+        // Merging again changes nothing, whatever ids the other side kept
+        let theirs = record(vec![revision(0, 10, "a"), revision(1, 15, "c")]);
+        ours.union(theirs, DEFAULT_HISTORY_DEPTH);
+        assert_eq!(summary(&ours), vec![(0, 10), (2, 15), (1, 20)]);
+    }
+
+    #[test]
+    fn union_drops_the_oldest_revisions_past_depth() {
+        let mut ours = record(vec![revision(0, 10, "a"), revision(1, 30, "b")]);
+        let theirs = record(vec![revision(0, 20, "c")]);
+
+        ours.union(theirs, 2);
+
+        assert_eq!(summary(&ours), vec![(2, 20), (1, 30)]);
+    }
+}
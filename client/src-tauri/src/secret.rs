@@ -0,0 +1,47 @@
+use std::{fmt, ops::Deref};
+
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+/// 256 bits key, wiped from memory when dropped
+pub type SecretKey = Zeroizing<[u8; 32]>;
+
+/// Deciphered data, wiped from memory when dropped
+pub type SecretBytes = Zeroizing<Vec<u8>>;
+
+/// String holding deciphered data, wiped from memory when dropped
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct SecretString(String);
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        SecretString(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        SecretString(value.to_string())
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString(***)")
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
@@ -0,0 +1,60 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{vault::Vault, watcher::VaultWatcher};
+
+/// Seconds without any command after which vaults get locked, unless configured otherwise
+pub const DEFAULT_AUTO_LOCK: u64 = 5 * 60;
+
+/// The unlocked vault of the logged in user, along with the last time it was used
+pub struct Session {
+    pub vault: Vault,
+    last_activity: Instant,
+    /// Sends changes of the vault files to the UI while the session lasts
+    _watcher: Option<VaultWatcher>,
+}
+
+pub type CurrentUserState = Mutex<Option<Session>>;
+
+impl Session {
+    pub fn new(vault: Vault, watcher: Option<VaultWatcher>) -> Self {
+        Session {
+            vault,
+            last_activity: Instant::now(),
+            _watcher: watcher,
+        }
+    }
+
+    /// Postpones the auto lock, called by every command using the vault
+    pub fn touch(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    pub fn is_idle(&self) -> bool {
+        match self.vault.config.auto_lock {
+            Some(seconds) => self.last_activity.elapsed() >= Duration::from_secs(seconds),
+            None => false,
+        }
+    }
+}
+
+/// Forgets the logged in user, the vault key is wiped from memory as soon as commands still
+/// running are done with it. Returns whether a user was logged in
+pub fn lock(current_user_state: &CurrentUserState) -> bool {
+    current_user_state.lock().unwrap().take().is_some()
+}
+
+/// Locks the vault if it has not been used for longer than its auto lock delay, returns whether
+/// it got locked
+pub fn lock_if_idle(current_user_state: &CurrentUserState) -> bool {
+    let mut current_user = current_user_state.lock().unwrap();
+
+    if current_user.as_ref().is_some_and(Session::is_idle) {
+        *current_user = None;
+        true
+    } else {
+        false
+    }
+}
@@ -0,0 +1,493 @@
+use std::{collections::HashSet, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::generator::WORDLIST;
+
+/// Most common passwords, by rank
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456",
+    "password",
+    "12345678",
+    "qwerty",
+    "123456789",
+    "12345",
+    "1234",
+    "111111",
+    "1234567",
+    "dragon",
+    "123123",
+    "baseball",
+    "abc123",
+    "football",
+    "monkey",
+    "letmein",
+    "696969",
+    "shadow",
+    "master",
+    "666666",
+    "qwertyuiop",
+    "123321",
+    "mustang",
+    "1234567890",
+    "michael",
+    "654321",
+    "superman",
+    "1qaz2wsx",
+    "7777777",
+    "121212",
+    "000000",
+    "qazwsx",
+    "123qwe",
+    "killer",
+    "trustno1",
+    "jordan",
+    "jennifer",
+    "zxcvbnm",
+    "asdfgh",
+    "hunter",
+    "buster",
+    "soccer",
+    "harley",
+    "batman",
+    "andrew",
+    "tigger",
+    "sunshine",
+    "iloveyou",
+    "2000",
+    "charlie",
+    "robert",
+    "thomas",
+    "hockey",
+    "ranger",
+    "daniel",
+    "starwars",
+    "112233",
+    "george",
+    "computer",
+    "michelle",
+    "jessica",
+    "pepper",
+    "1111",
+    "zxcvbn",
+    "555555",
+    "11111111",
+    "131313",
+    "freedom",
+    "777777",
+    "pass",
+    "maggie",
+    "159753",
+    "aaaaaa",
+    "ginger",
+    "princess",
+    "joshua",
+    "cheese",
+    "amanda",
+    "summer",
+    "love",
+    "ashley",
+    "nicole",
+    "chelsea",
+    "biteme",
+    "matthew",
+    "access",
+    "yankees",
+    "987654321",
+    "dallas",
+    "austin",
+    "thunder",
+    "taylor",
+    "matrix",
+    "admin",
+    "welcome",
+    "login",
+    "passw0rd",
+    "master123",
+    "solo",
+    "azerty",
+    "whatever",
+    "dragon123",
+    "qwerty123",
+    "password1",
+    "password123",
+    "secret",
+    "flower",
+    "hello",
+    "hottie",
+    "lovely",
+    "ninja",
+    "mynoob",
+    "1q2w3e4r",
+    "football1",
+];
+
+/// Rows of QWERTY and AZERTY keyboards, walking along one of them is a common pattern
+const KEYBOARD_ROWS: &[&str] = &[
+    "qwertyuiop",
+    "asdfghjkl",
+    "zxcvbnm",
+    "azertyuiop",
+    "qsdfghjklm",
+];
+
+/// Longest password of the dictionaries, longer parts can't match them
+const MAX_WORD_LEN: usize = 12;
+
+/// Characters of a password looked at for patterns, like zxcvbn does
+const MAX_ANALYSED_LENGTH: usize = 100;
+
+/// Guesses per second of an offline attack against a slow hash
+const GUESSES_PER_SECOND: f64 = 1e4;
+
+/// Estimated strength of a password, in the spirit of zxcvbn
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Strength {
+    /// From 0, too guessable, to 4, very unguessable
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub crack_time_seconds: f64,
+    pub crack_time_display: String,
+    pub feedback: Feedback,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Feedback {
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pattern {
+    CommonPassword,
+    Word,
+    Sequence,
+    Repeat,
+    Keyboard,
+    Year,
+    Bruteforce,
+}
+
+/// Part of the password guessed as a whole, `guesses_log10` being the log10 of the guesses needed
+#[derive(Clone, Copy)]
+struct Match {
+    start: usize,
+    end: usize,
+    pattern: Pattern,
+    guesses_log10: f64,
+}
+
+fn english_words() -> &'static HashSet<&'static str> {
+    static WORDS: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| WORDLIST.lines().collect())
+}
+
+pub fn estimate(password: &str) -> Strength {
+    let chars: Vec<char> = password.chars().collect();
+
+    // Matching is cubic in the length, so long values such as pasted keys only have their start
+    // analysed, the rest counts as bruteforced characters
+    let (analysed, rest) = chars.split_at(chars.len().min(MAX_ANALYSED_LENGTH));
+    let matches = find_matches(analysed);
+    let (mut guesses_log10, sequence) = minimum_guesses(analysed, &matches);
+    guesses_log10 += rest
+        .iter()
+        .map(|c| (cardinality(*c) as f64).log10())
+        .sum::<f64>();
+
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+
+    // Overflows to infinity for long passwords, which JSON can't hold
+    let crack_time_seconds = (10f64.powf(guesses_log10) / GUESSES_PER_SECOND).min(f64::MAX);
+
+    Strength {
+        score,
+        guesses_log10,
+        crack_time_seconds,
+        crack_time_display: display_time(crack_time_seconds),
+        feedback: feedback(&chars, score, &sequence),
+    }
+}
+
+fn find_matches(chars: &[char]) -> Vec<Match> {
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    if lower.len() != chars.len() {
+        // Lowercasing changed the length, matching would not line up with the password
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+
+    for start in 0..chars.len() {
+        for end in start + 1..=chars.len() {
+            if end - start <= MAX_WORD_LEN {
+                matches.extend(dictionary_match(chars, &lower, start, end));
+            }
+
+            if end - start < 3 {
+                continue;
+            }
+
+            let token = &lower[start..end];
+
+            if token.iter().all(|c| *c == token[0]) {
+                matches.push(Match {
+                    start,
+                    end,
+                    pattern: Pattern::Repeat,
+                    guesses_log10: (cardinality(token[0]) as f64 * token.len() as f64).log10(),
+                });
+            } else if is_sequence(token) {
+                matches.push(Match {
+                    start,
+                    end,
+                    pattern: Pattern::Sequence,
+                    guesses_log10: (cardinality(token[0]) as f64 * token.len() as f64).log10(),
+                });
+            } else if token.len() >= 4 && is_keyboard_walk(token) {
+                matches.push(Match {
+                    start,
+                    end,
+                    pattern: Pattern::Keyboard,
+                    guesses_log10: (KEYBOARD_ROWS.len() as f64 * 2.0 * token.len() as f64).log10(),
+                });
+            }
+
+            if token.len() == 4 {
+                let year: String = token.iter().collect();
+                if let Ok(year) = year.parse::<u32>() {
+                    if (1900..2100).contains(&year) {
+                        matches.push(Match {
+                            start,
+                            end,
+                            pattern: Pattern::Year,
+                            guesses_log10: 200f64.log10(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    matches
+}
+
+/// Matches `chars[start..end]` against the common passwords and english words
+fn dictionary_match(chars: &[char], lower: &[char], start: usize, end: usize) -> Option<Match> {
+    let token: String = lower[start..end].iter().map(|c| unleet(*c)).collect();
+    let uppercase_factor = if chars[start..end] == lower[start..end] {
+        0.0
+    } else {
+        2f64.log10()
+    };
+
+    let (pattern, guesses) = if let Some(rank) = COMMON_PASSWORDS.iter().position(|p| *p == token) {
+        (Pattern::CommonPassword, rank + 1)
+    } else if end - start >= 3 && english_words().contains(token.as_str()) {
+        (Pattern::Word, english_words().len())
+    } else {
+        return None;
+    };
+
+    Some(Match {
+        start,
+        end,
+        pattern,
+        guesses_log10: (guesses as f64).log10() + uppercase_factor,
+    })
+}
+
+/// Finds the cheapest way to guess the whole password out of its matches and bruteforced
+/// characters, returns its log10 guesses along with the patterns used
+fn minimum_guesses(chars: &[char], matches: &[Match]) -> (f64, Vec<Match>) {
+    if chars.is_empty() {
+        return (0.0, Vec::new());
+    }
+
+    // best[i] is the cheapest way to guess the first i characters
+    let mut best: Vec<(f64, Option<Match>)> = vec![(f64::INFINITY, None); chars.len() + 1];
+    best[0].0 = 0.0;
+
+    for end in 1..=chars.len() {
+        let bruteforce = Match {
+            start: end - 1,
+            end,
+            pattern: Pattern::Bruteforce,
+            guesses_log10: (cardinality(chars[end - 1]) as f64).log10(),
+        };
+
+        for m in matches
+            .iter()
+            .filter(|m| m.end == end)
+            .chain(std::iter::once(&bruteforce))
+        {
+            let guesses = best[m.start].0 + m.guesses_log10;
+            if guesses < best[end].0 {
+                best[end] = (guesses, Some(*m));
+            }
+        }
+    }
+
+    let mut sequence = Vec::new();
+    let mut end = chars.len();
+    while let Some(m) = best[end].1 {
+        sequence.push(m);
+        end = m.start;
+    }
+    sequence.reverse();
+
+    (best[chars.len()].0, sequence)
+}
+
+fn feedback(chars: &[char], score: u8, sequence: &[Match]) -> Feedback {
+    if chars.is_empty() {
+        return Feedback {
+            warning: None,
+            suggestions: vec!["Use a few words, avoid common phrases".to_string()],
+        };
+    }
+
+    if score > 2 {
+        return Feedback::default();
+    }
+
+    // The longest pattern is the one most worth fixing
+    let warning = sequence
+        .iter()
+        .filter(|m| m.pattern != Pattern::Bruteforce)
+        .max_by_key(|m| m.end - m.start)
+        .and_then(|m| match m.pattern {
+            Pattern::CommonPassword => Some("This is a very common password"),
+            Pattern::Word => Some("A word by itself is easy to guess"),
+            Pattern::Sequence => Some("Sequences like abc or 6543 are easy to guess"),
+            Pattern::Repeat => Some("Repeats like \"aaa\" are easy to guess"),
+            Pattern::Keyboard => Some("Straight rows of keys are easy to guess"),
+            Pattern::Year => Some("Recent years are easy to guess"),
+            Pattern::Bruteforce => None,
+        })
+        .map(String::from);
+
+    let mut suggestions = vec!["Add another word or two, uncommon words are better".to_string()];
+    if chars.len() < 12 {
+        suggestions.push("Use a longer password".to_string());
+    }
+    // Only dictionary matches are looked up with substitutions undone
+    let substituted = sequence.iter().any(|m| {
+        matches!(m.pattern, Pattern::CommonPassword | Pattern::Word)
+            && chars[m.start..m.end].iter().any(|c| unleet(*c) != *c)
+    });
+    if substituted {
+        suggestions
+            .push("Predictable substitutions like '@' instead of 'a' don't help".to_string());
+    }
+
+    Feedback {
+        warning,
+        suggestions,
+    }
+}
+
+/// Number of characters of the class of `c`
+fn cardinality(c: char) -> u32 {
+    match c {
+        'a'..='z' | 'A'..='Z' => 26,
+        '0'..='9' => 10,
+        c if c.is_ascii() => 33,
+        _ => 100,
+    }
+}
+
+/// Undoes common l33t speak substitutions
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' => 't',
+        c => c,
+    }
+}
+
+fn is_sequence(token: &[char]) -> bool {
+    let delta = token[1] as i64 - token[0] as i64;
+    delta.abs() == 1 && token.windows(2).all(|w| w[1] as i64 - w[0] as i64 == delta)
+}
+
+fn is_keyboard_walk(token: &[char]) -> bool {
+    let token: String = token.iter().collect();
+    let reversed: String = token.chars().rev().collect();
+
+    KEYBOARD_ROWS
+        .iter()
+        .any(|row| row.contains(&token) || row.contains(&reversed))
+}
+
+fn display_time(seconds: f64) -> String {
+    const MINUTE: f64 = 60.0;
+    const HOUR: f64 = MINUTE * 60.0;
+    const DAY: f64 = HOUR * 24.0;
+    const MONTH: f64 = DAY * 31.0;
+    const YEAR: f64 = MONTH * 12.0;
+    const CENTURY: f64 = YEAR * 100.0;
+
+    let (value, unit) = match seconds {
+        s if s < 1.0 => return "less than a second".to_string(),
+        s if s < MINUTE => (s, "second"),
+        s if s < HOUR => (s / MINUTE, "minute"),
+        s if s < DAY => (s / HOUR, "hour"),
+        s if s < MONTH => (s / DAY, "day"),
+        s if s < YEAR => (s / MONTH, "month"),
+        s if s < CENTURY => (s / YEAR, "year"),
+        _ => return "centuries".to_string(),
+    };
+
+    let value = value.round() as u64;
+    format!("{} {}{}", value, unit, if value == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_passwords_round_trip_through_json() {
+        for length in [250, 400, 5000] {
+            let password: String = (0..length)
+                .map(|i| char::from(b'!' + (i * 7 % 94) as u8))
+                .collect();
+            let strength = estimate(&password);
+            assert!(strength.crack_time_seconds.is_finite());
+
+            let json = serde_json::to_string(&strength).unwrap();
+            let read: Strength = serde_json::from_str(&json).unwrap();
+            assert_eq!(read.crack_time_seconds, strength.crack_time_seconds);
+        }
+    }
+
+    #[test]
+    fn suggests_against_substitutions_only_when_there_are_some() {
+        let substitutions = "Predictable substitutions like '@' instead of 'a' don't help";
+
+        let plain = estimate("password");
+        assert!(!plain
+            .feedback
+            .suggestions
+            .iter()
+            .any(|s| s == substitutions));
+
+        let substituted = estimate("p@ssw0rd");
+        assert!(substituted
+            .feedback
+            .suggestions
+            .iter()
+            .any(|s| s == substitutions));
+    }
+}
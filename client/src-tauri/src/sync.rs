@@ -0,0 +1,1044 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use git2::{
+    build::CheckoutBuilder, Commit, Cred, CredentialType, FetchOptions, FileMode, ObjectType, Oid,
+    PushOptions, RemoteCallbacks, Repository, Signature, Tree, TreeWalkMode, TreeWalkResult,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    error::PassyError,
+    git::{self, git_error, open_repo},
+    names::{NamesIndex, NAMES_INDEX_FILE},
+    password::{Password, PASSWORD_FIELD},
+    revisions::{self, history_file},
+    secret::SecretString,
+    trash::{self, TrashedPassword, TRASH_DIR, TRASH_INDEX_FILE},
+    vault::{self, Vault, VaultConfig, KEYS_FILE, VAULT_CONFIG_FILE},
+};
+
+/// Name the fetched branch of the remote is kept under
+const REMOTE_NAME: &str = "origin";
+
+/// Ref the unlock material of the vault is synced under, out of the history of the vault.
+///
+/// It points to a single commit holding [`KEYS_FILE`] which is replaced each time the master
+/// password changes, so that the remote never keeps data keys wrapped by old passwords. Locally
+/// it is the unlock material last pushed or pulled, which tells the side that changed it since.
+const KEYS_REF: &str = "refs/passy/keys";
+
+/// Ref the unlock material of the remote is fetched to
+const REMOTE_KEYS_REF: &str = "refs/passy/origin-keys";
+
+/// Ref the unlock material is pushed from, it is only synced once the push succeeded
+const PUSHED_KEYS_REF: &str = "refs/passy/pushed-keys";
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncSide {
+    Ours,
+    Theirs,
+}
+
+/// Side chosen by the user for a conflict, `field` is `None` for conflicts on a whole entry
+#[derive(Deserialize)]
+pub struct ConflictResolution {
+    pub path: String,
+    pub field: Option<String>,
+    pub side: SyncSide,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SyncConflict {
+    /// The same field changed differently on both sides, `None` being a removed field
+    Field {
+        path: String,
+        field: String,
+        ours: Option<SecretString>,
+        theirs: Option<SecretString>,
+    },
+    /// The entry was deleted on one side and changed on the other
+    Entry { path: String, deleted_ours: bool },
+    /// A setting of the vault changed differently on both sides, resolved with the vault config
+    /// file as path
+    Setting {
+        field: String,
+        ours: Value,
+        theirs: Value,
+    },
+    /// The master password was changed on both sides since the last sync, resolved with the keys
+    /// file as path and no field. Times are the Unix times of the changes.
+    MasterPassword {
+        ours_changed: Option<u64>,
+        theirs_changed: Option<u64>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PullResult {
+    UpToDate,
+    Updated {
+        /// Passwords left as they are on this device, as their file on one side can't be
+        /// deciphered
+        skipped: Vec<String>,
+    },
+    /// Nothing was changed, the pull has to be made again with a resolution for each conflict
+    Conflicts {
+        conflicts: Vec<SyncConflict>,
+    },
+}
+
+fn remote_url(vault: &Vault) -> Result<&str, PassyError> {
+    vault
+        .config
+        .remote
+        .as_deref()
+        .ok_or(PassyError::NoSyncRemote(vault.username.clone()))
+}
+
+fn head_branch(repo: &Repository) -> Result<String, PassyError> {
+    let head = repo.head().map_err(git_error)?;
+    head.shorthand()
+        .map(str::to_string)
+        .ok_or(PassyError::GitError("HEAD is not a branch".to_string()))
+}
+
+/// Authenticates with the SSH agent or the git credential helpers
+fn callbacks(repo: &Repository) -> RemoteCallbacks<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) {
+            Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            Cred::credential_helper(&repo.config()?, url, username)
+        } else {
+            Cred::default()
+        }
+    });
+    callbacks
+}
+
+/// Fetches the changes of the remote without bringing them into the vault, which is done by
+/// [`pull`]. The vault doesn't need to be locked meanwhile.
+pub fn fetch(vault: &Vault) -> Result<(), PassyError> {
+    let repo = open_repo(vault)?;
+    let url = remote_url(vault)?;
+    let branch = head_branch(&repo)?;
+
+    fetch_refs(&repo, url, &branch)?;
+    Ok(())
+}
+
+fn tracking_ref(branch: &str) -> String {
+    format!("refs/remotes/{}/{}", REMOTE_NAME, branch)
+}
+
+/// Fetches `branch` and the unlock material from the remote, returns the last commit of the
+/// branch if the remote has it
+fn fetch_refs(repo: &Repository, url: &str, branch: &str) -> Result<Option<Oid>, PassyError> {
+    let tracking = tracking_ref(branch);
+
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks(repo));
+    repo.remote_anonymous(url)
+        .and_then(|mut remote| {
+            remote.fetch(
+                &[
+                    format!("+refs/heads/{}:{}", branch, tracking),
+                    format!("+{}:{}", KEYS_REF, REMOTE_KEYS_REF),
+                ],
+                Some(&mut options),
+                None,
+            )
+        })
+        .map_err(git_error)?;
+
+    Ok(repo.refname_to_id(&tracking).ok())
+}
+
+/// Sends the history of the vault to its remote, which must not have changes that weren't pulled.
+///
+/// Changes that aren't committed yet aren't sent, the vault doesn't need to be locked meanwhile.
+pub fn push(vault: &Vault) -> Result<(), PassyError> {
+    let repo = open_repo(vault)?;
+    let url = remote_url(vault)?;
+
+    let branch = head_branch(&repo)?;
+    let ours = repo.refname_to_id("HEAD").map_err(git_error)?;
+    if let Some(theirs) = fetch_refs(&repo, url, &branch)? {
+        if theirs != ours && !repo.graph_descendant_of(ours, theirs).map_err(git_error)? {
+            return Err(PassyError::SyncPullNeeded);
+        }
+    }
+
+    let config = VaultConfig::read(&vault.path)?;
+    let keys = keys_content(&config)?;
+    let synced = repo.refname_to_id(KEYS_REF).ok();
+    let mut refspecs = vec![format!("refs/heads/{0}:refs/heads/{0}", branch)];
+    let pushed_keys = match repo.refname_to_id(REMOTE_KEYS_REF).ok() {
+        Some(theirs) if keys_in_commit(&repo, &config, theirs)? == keys => None,
+        // The master password was changed on the remote since the last sync
+        Some(theirs) if Some(theirs) != synced => return Err(PassyError::SyncPullNeeded),
+        _ => {
+            let pushed = commit_keys(&repo, vault, &keys)?;
+            refspecs.push(format!("+{}:{}", PUSHED_KEYS_REF, KEYS_REF));
+            Some(pushed)
+        }
+    };
+
+    let rejection = RefCell::new(None);
+    {
+        let mut callbacks = callbacks(&repo);
+        callbacks.push_update_reference(|_, status| {
+            if let Some(status) = status {
+                *rejection.borrow_mut() = Some(status.to_string());
+            }
+            Ok(())
+        });
+
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+        repo.remote_anonymous(url)
+            .and_then(|mut remote| remote.push(&refspecs, Some(&mut options)))
+            .map_err(git_error)?;
+    }
+
+    if let Some(status) = rejection.into_inner() {
+        return Err(PassyError::GitError(status));
+    }
+
+    if let Some(pushed) = pushed_keys {
+        repo.reference(KEYS_REF, pushed, true, "Push unlock material")
+            .map_err(git_error)?;
+    }
+
+    Ok(())
+}
+
+fn keys_content(config: &VaultConfig) -> Result<Vec<u8>, PassyError> {
+    config
+        .file_content(KEYS_FILE)
+        .ok_or(PassyError::FailedToReadVaultConfig(PathBuf::from(
+            KEYS_FILE,
+        )))
+}
+
+/// Unlock material held by the commit `id`, written the way `config` would write it
+fn keys_in_commit(repo: &Repository, config: &VaultConfig, id: Oid) -> Result<Vec<u8>, PassyError> {
+    let entry = repo
+        .find_commit(id)
+        .and_then(|commit| commit.tree())
+        .and_then(|tree| tree.get_path(Path::new(KEYS_FILE)))
+        .map_err(git_error)?;
+    let blob = repo.find_blob(entry.id()).map_err(git_error)?;
+
+    keys_content(&config.with_file_content(KEYS_FILE, blob.content())?)
+}
+
+/// Points [`PUSHED_KEYS_REF`] to a new commit without parents holding `keys`, returns its id
+fn commit_keys(repo: &Repository, vault: &Vault, keys: &[u8]) -> Result<Oid, PassyError> {
+    let blob = repo.blob(keys).map_err(git_error)?;
+    let mut builder = repo.treebuilder(None).map_err(git_error)?;
+    builder
+        .insert(KEYS_FILE, blob, FileMode::Blob.into())
+        .map_err(git_error)?;
+    let tree = builder
+        .write()
+        .and_then(|tree| repo.find_tree(tree))
+        .map_err(git_error)?;
+
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now(&vault.username, "passy@localhost"))
+        .map_err(git_error)?;
+    let commit = repo
+        .commit(None, &signature, &signature, "Unlock material", &tree, &[])
+        .map_err(git_error)?;
+
+    repo.reference(PUSHED_KEYS_REF, commit, true, "Unlock material")
+        .map_err(git_error)?;
+
+    Ok(commit)
+}
+
+/// Unlock material of the remote as it should be taken after the pull
+struct KeysMerge {
+    /// Commit of the remote holding it, synced once the pull succeeded
+    theirs: Oid,
+    /// Replaces the unlock material of the vault
+    keys: Option<Vec<u8>>,
+}
+
+/// Three-way merge of the unlock material, taken from the side that changed the master password
+/// since the last sync. Conflicts without resolution are added to `conflicts`.
+fn merge_keys(
+    repo: &Repository,
+    config: &VaultConfig,
+    resolutions: &[ConflictResolution],
+    conflicts: &mut Vec<SyncConflict>,
+) -> Result<Option<KeysMerge>, PassyError> {
+    let theirs_id = match repo.refname_to_id(REMOTE_KEYS_REF) {
+        Ok(id) => id,
+        Err(_) => return Ok(None),
+    };
+    let synced_id = repo.refname_to_id(KEYS_REF).ok();
+    if synced_id == Some(theirs_id) {
+        return Ok(None);
+    }
+
+    let ours = keys_content(config)?;
+    let theirs = keys_in_commit(repo, config, theirs_id)?;
+    let base = match synced_id {
+        Some(id) => Some(keys_in_commit(repo, config, id)?),
+        None => None,
+    };
+
+    let take_theirs = if ours == theirs {
+        false
+    } else if base.as_ref() == Some(&ours) {
+        true
+    } else {
+        match resolution(resolutions, KEYS_FILE, None) {
+            Some(side) => matches!(side, SyncSide::Theirs),
+            None => {
+                conflicts.push(SyncConflict::MasterPassword {
+                    ours_changed: config.keys_changed(),
+                    theirs_changed: config.with_file_content(KEYS_FILE, &theirs)?.keys_changed(),
+                });
+                return Ok(None);
+            }
+        }
+    };
+
+    Ok(Some(KeysMerge {
+        theirs: theirs_id,
+        keys: take_theirs.then_some(theirs),
+    }))
+}
+
+/// Brings the changes of the remote, as of the last [`fetch`], into the vault.
+///
+/// Entries and settings changed on both sides are merged field by field, entries being
+/// deciphered for that. Fields changed on both sides are taken from `resolutions`, if any of them
+/// has none nothing is changed and the conflicts are returned. The unlock material is taken from
+/// the remote if only its master password changed since the last sync.
+pub fn pull(vault: &Vault, resolutions: &[ConflictResolution]) -> Result<PullResult, PassyError> {
+    let repo = open_repo(vault)?;
+    git::commit(vault, "Save changes before sync")?;
+
+    let branch = head_branch(&repo)?;
+    let theirs = repo.refname_to_id(&tracking_ref(&branch)).ok();
+
+    let mut conflicts = Vec::new();
+    let config = VaultConfig::read(&vault.path)?;
+    let keys = merge_keys(&repo, &config, resolutions, &mut conflicts)?;
+
+    let mut result = match theirs {
+        Some(theirs) => pull_branch(&repo, vault, theirs, resolutions, conflicts)?,
+        None if conflicts.is_empty() => PullResult::UpToDate,
+        None => PullResult::Conflicts { conflicts },
+    };
+    if let PullResult::Conflicts { .. } = result {
+        return Ok(result);
+    }
+
+    if let Some(keys) = keys {
+        if let Some(content) = keys.keys {
+            // Settings may have been changed by the merge
+            VaultConfig::read(&vault.path)?
+                .with_file_content(KEYS_FILE, &content)?
+                .write(&vault.path)?;
+            result = PullResult::Updated {
+                skipped: Vec::new(),
+            };
+        }
+
+        repo.reference(KEYS_REF, keys.theirs, true, "Pull unlock material")
+            .map_err(git_error)?;
+    }
+
+    Ok(result)
+}
+
+fn pull_branch(
+    repo: &Repository,
+    vault: &Vault,
+    theirs: Oid,
+    resolutions: &[ConflictResolution],
+    conflicts: Vec<SyncConflict>,
+) -> Result<PullResult, PassyError> {
+    let theirs = repo.find_commit(theirs).map_err(git_error)?;
+    let ours = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(git_error)?;
+
+    if ours.id() == theirs.id()
+        || repo
+            .graph_descendant_of(ours.id(), theirs.id())
+            .map_err(git_error)?
+    {
+        if !conflicts.is_empty() {
+            return Ok(PullResult::Conflicts { conflicts });
+        }
+
+        return Ok(PullResult::UpToDate);
+    }
+
+    if repo
+        .graph_descendant_of(theirs.id(), ours.id())
+        .map_err(git_error)?
+    {
+        if !conflicts.is_empty() {
+            return Ok(PullResult::Conflicts { conflicts });
+        }
+
+        fast_forward(repo, &theirs)?;
+        return Ok(PullResult::Updated {
+            skipped: Vec::new(),
+        });
+    }
+
+    merge(repo, vault, &ours, &theirs, resolutions, conflicts)
+}
+
+/// Checks out the commit of the remote, the unlock material and the settings of this device are
+/// untouched as they aren't committed
+fn fast_forward(repo: &Repository, theirs: &Commit) -> Result<(), PassyError> {
+    repo.checkout_tree(theirs.as_object(), Some(CheckoutBuilder::new().force()))
+        .and_then(|_| repo.head())
+        .and_then(|mut head| head.set_target(theirs.id(), "Fast-forward from remote"))
+        .map_err(git_error)?;
+
+    Ok(())
+}
+
+fn merge(
+    repo: &Repository,
+    vault: &Vault,
+    ours: &Commit,
+    theirs: &Commit,
+    resolutions: &[ConflictResolution],
+    mut conflicts: Vec<SyncConflict>,
+) -> Result<PullResult, PassyError> {
+    // Histories started on different devices have no common commit
+    let base = match repo.merge_base(ours.id(), theirs.id()) {
+        Ok(base) => Some(
+            repo.find_commit(base)
+                .and_then(|base| base.tree())
+                .map_err(git_error)?,
+        ),
+        Err(_) => None,
+    };
+    let ours_tree = ours.tree().map_err(git_error)?;
+    let theirs_tree = theirs.tree().map_err(git_error)?;
+
+    // A base that can't be deciphered is treated as missing, which only leads to more conflicts
+    let mut skipped = BTreeSet::new();
+    let base_entries = match &base {
+        Some(base) => entries_in_tree(repo, vault, base, &mut BTreeSet::new())?,
+        None => HashMap::new(),
+    };
+    let mut ours_entries = entries_in_tree(repo, vault, &ours_tree, &mut skipped)?;
+    let mut theirs_entries = entries_in_tree(repo, vault, &theirs_tree, &mut skipped)?;
+
+    let paths: BTreeSet<String> = ours_entries
+        .keys()
+        .chain(theirs_entries.keys())
+        .filter(|path| !skipped.contains(*path))
+        .cloned()
+        .collect();
+
+    let mut changes = Vec::new();
+    for path in paths {
+        let ours = ours_entries.remove(&path);
+        let ours_fields = ours.as_ref().map(|ours| ours.data().get().clone());
+
+        let merged = merge_entry(
+            &path,
+            base_entries.get(&path),
+            ours,
+            theirs_entries.remove(&path),
+            resolutions,
+            &mut conflicts,
+        );
+
+        if merged.as_ref().map(|merged| merged.data().get()) != ours_fields.as_ref() {
+            changes.push((path, merged));
+        }
+    }
+
+    let config = merge_settings(
+        repo,
+        vault,
+        base.as_ref(),
+        &theirs_tree,
+        resolutions,
+        &mut conflicts,
+    )?;
+
+    if !conflicts.is_empty() {
+        return Ok(PullResult::Conflicts { conflicts });
+    }
+
+    let vault = apply_config(vault, config)?;
+    // The remote trash comes first, passwords deleted on the remote are already in it
+    let trashed = merge_trash(repo, &vault, base.as_ref(), &theirs_tree)?;
+
+    for (path, merged) in changes {
+        match merged {
+            Some(password) => password.write(&vault)?,
+            // Passwords deleted on the remote can still be restored from the trash
+            None if Password::exists(&vault, &path)? => {
+                match trashed.iter().find(|trashed| trashed.path == path) {
+                    Some(trashed) => trash::remove_trashed(&vault, &path, &trashed.id)?,
+                    None => trash::move_to_trash(&vault, &path)?,
+                }
+            }
+            None => {}
+        }
+    }
+
+    merge_histories(repo, &vault, base.as_ref(), &theirs_tree, &mut skipped)?;
+
+    git::merge_commit(&vault, "Merge remote changes", theirs.id())?;
+
+    Ok(PullResult::Updated {
+        skipped: skipped.into_iter().collect(),
+    })
+}
+
+/// Brings the changes made to the trash of `theirs` since `base` into the trash: the passwords
+/// deleted there are added, the ones restored or purged are removed. Returns the added ones.
+fn merge_trash(
+    repo: &Repository,
+    vault: &Vault,
+    base: Option<&Tree>,
+    theirs: &Tree,
+) -> Result<Vec<TrashedPassword>, PassyError> {
+    let index_file = Path::new(TRASH_DIR).join(TRASH_INDEX_FILE);
+    let read_index = |tree: &Tree| match tree.get_path(&index_file) {
+        Ok(entry) => {
+            let blob = repo.find_blob(entry.id()).map_err(git_error)?;
+            trash::read_index(vault, &index_file, blob.content())
+        }
+        Err(_) => Ok(Vec::new()),
+    };
+
+    // A base that can't be deciphered is treated as missing, which only keeps more in the trash
+    let base_trash = match base {
+        Some(base) => read_index(base).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let theirs_trash = read_index(theirs)?;
+    let ours_trash = trash::list(vault)?;
+    let in_trash = |trash: &[TrashedPassword], id: &str| trash.iter().any(|t| t.id == id);
+
+    for trashed in &base_trash {
+        if !in_trash(&theirs_trash, &trashed.id) && in_trash(&ours_trash, &trashed.id) {
+            trash::remove(vault, &trashed.id)?;
+        }
+    }
+
+    let mut added = Vec::new();
+    for trashed in theirs_trash {
+        if in_trash(&base_trash, &trashed.id) || in_trash(&ours_trash, &trashed.id) {
+            continue;
+        }
+
+        let file = Path::new(TRASH_DIR).join(trash::trashed_file_name(&trashed.id));
+        let content = match theirs.get_path(&file) {
+            Ok(entry) => repo.find_blob(entry.id()).map_err(git_error)?,
+            Err(_) => continue,
+        };
+        let history = match theirs.get_path(&history_file(&file)) {
+            Ok(entry) => Some(repo.find_blob(entry.id()).map_err(git_error)?),
+            Err(_) => None,
+        };
+
+        trash::add(
+            vault,
+            trashed.clone(),
+            content.content(),
+            history.as_ref().map(|history| history.content()),
+        )?;
+        added.push(trashed);
+    }
+
+    Ok(added)
+}
+
+/// Adds the revisions of the passwords in `theirs` to their history, histories unchanged since
+/// `base` are left out. Paths of the histories that can't be deciphered are added to
+/// `unreadable`.
+fn merge_histories(
+    repo: &Repository,
+    vault: &Vault,
+    base: Option<&Tree>,
+    theirs: &Tree,
+    unreadable: &mut BTreeSet<String>,
+) -> Result<(), PassyError> {
+    for (path, file) in files_in_tree(repo, vault, theirs)? {
+        let history = history_file(Path::new(&file));
+        let entry = match theirs.get_path(&history) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let unchanged = base
+            .and_then(|base| base.get_path(&history).ok())
+            .is_some_and(|base| base.id() == entry.id());
+        if unchanged || unreadable.contains(&path) || !Password::exists(vault, &path)? {
+            continue;
+        }
+
+        let blob = repo.find_blob(entry.id()).map_err(git_error)?;
+        match revisions::merge(vault, &path, &history, blob.content()) {
+            Ok(()) => {}
+            Err(PassyError::FailedToReadHistory(_)) => {
+                unreadable.insert(path);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Three-way merge of the entry at `path`, conflicts without resolution are added to `conflicts`
+fn merge_entry(
+    path: &str,
+    base: Option<&Password>,
+    ours: Option<Password>,
+    theirs: Option<Password>,
+    resolutions: &[ConflictResolution],
+    conflicts: &mut Vec<SyncConflict>,
+) -> Option<Password> {
+    let same = |a: Option<&Password>, b: Option<&Password>| {
+        a.map(|a| a.data().get()) == b.map(|b| b.data().get())
+    };
+
+    if same(ours.as_ref(), theirs.as_ref()) || same(theirs.as_ref(), base) {
+        return ours;
+    }
+    if same(ours.as_ref(), base) {
+        return theirs;
+    }
+
+    let (mut ours, theirs) = match (ours, theirs) {
+        (Some(ours), Some(theirs)) => (ours, theirs),
+        (ours, theirs) => {
+            return match resolution(resolutions, path, None) {
+                Some(SyncSide::Ours) => ours,
+                Some(SyncSide::Theirs) => theirs,
+                None => {
+                    conflicts.push(SyncConflict::Entry {
+                        path: path.to_string(),
+                        deleted_ours: ours.is_none(),
+                    });
+                    ours
+                }
+            };
+        }
+    };
+
+    let no_fields = HashMap::new();
+    let base_fields = base.map(|base| base.data().get()).unwrap_or(&no_fields);
+    let ours_fields = ours.data().get();
+    let theirs_fields = theirs.data().get();
+
+    let fields: BTreeSet<&String> = base_fields
+        .keys()
+        .chain(ours_fields.keys())
+        .chain(theirs_fields.keys())
+        .collect();
+
+    let mut merged = HashMap::new();
+    for field in fields {
+        let base_value = base_fields.get(field);
+        let ours_value = ours_fields.get(field);
+        let theirs_value = theirs_fields.get(field);
+
+        let value = if ours_value == theirs_value || theirs_value == base_value {
+            ours_value
+        } else if ours_value == base_value {
+            theirs_value
+        } else {
+            match resolution(resolutions, path, Some(field)) {
+                Some(SyncSide::Ours) => ours_value,
+                Some(SyncSide::Theirs) => theirs_value,
+                None => {
+                    conflicts.push(SyncConflict::Field {
+                        path: path.to_string(),
+                        field: field.clone(),
+                        ours: ours_value.cloned(),
+                        theirs: theirs_value.cloned(),
+                    });
+                    ours_value
+                }
+            }
+        };
+
+        if let Some(value) = value {
+            merged.insert(field.clone(), value.clone());
+        }
+    }
+
+    if merged.get(PASSWORD_FIELD) != ours.password() {
+        ours.password_changed = theirs.password_changed;
+    }
+    *ours.data_mut().get_mut() = merged;
+
+    Some(ours)
+}
+
+fn resolution(
+    resolutions: &[ConflictResolution],
+    path: &str,
+    field: Option<&str>,
+) -> Option<SyncSide> {
+    resolutions
+        .iter()
+        .find(|resolution| resolution.path == path && resolution.field.as_deref() == field)
+        .map(|resolution| resolution.side)
+}
+
+/// Passwords of the vault as they are in `tree`, whether names were hidden at the time or not.
+/// Paths of the files that can't be deciphered are added to `unreadable`.
+fn entries_in_tree(
+    repo: &Repository,
+    vault: &Vault,
+    tree: &Tree,
+    unreadable: &mut BTreeSet<String>,
+) -> Result<HashMap<String, Password>, PassyError> {
+    let mut entries = HashMap::new();
+    for (path, file) in files_in_tree(repo, vault, tree)? {
+        let entry = match tree.get_path(Path::new(&file)) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let blob = repo.find_blob(entry.id()).map_err(git_error)?;
+        match Password::from_content(vault, Path::new(&file), blob.content(), path.clone()) {
+            Ok(password) => {
+                entries.insert(password.path.clone(), password);
+            }
+            Err(_) => {
+                unreadable.insert(path);
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Paths of the passwords of the vault in `tree` along with their files in it
+fn files_in_tree(
+    repo: &Repository,
+    vault: &Vault,
+    tree: &Tree,
+) -> Result<Vec<(String, String)>, PassyError> {
+    let files: Vec<(String, String)> = match tree.get_path(Path::new(NAMES_INDEX_FILE)) {
+        Ok(entry) => {
+            let blob = repo.find_blob(entry.id()).map_err(git_error)?;
+            NamesIndex::from_sealed(&vault.key, blob.content(), Path::new(NAMES_INDEX_FILE))?
+                .entries()
+                .map(|(path, file)| (path.clone(), file.to_string() + ".passy"))
+                .collect()
+        }
+        Err(_) => {
+            let mut files = Vec::new();
+            tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+                let hidden = entry.name().is_some_and(|name| name.starts_with('.'));
+                if entry.kind() == Some(ObjectType::Tree) && hidden {
+                    return TreeWalkResult::Skip;
+                }
+
+                if entry.kind() == Some(ObjectType::Blob) {
+                    if let Some(name) = entry.name().and_then(|n| n.strip_suffix(".passy")) {
+                        files.push((root.to_string() + name, format!("{}{}.passy", root, name)));
+                    }
+                }
+                TreeWalkResult::Ok
+            })
+            .map_err(git_error)?;
+            files
+        }
+    };
+
+    Ok(files)
+}
+
+/// Settings of the vault kept in [`VAULT_CONFIG_FILE`] as they are in `tree`, if it has them
+fn settings_in_tree(
+    repo: &Repository,
+    config: &VaultConfig,
+    tree: &Tree,
+) -> Result<Option<Map<String, Value>>, PassyError> {
+    let entry = match tree.get_path(Path::new(VAULT_CONFIG_FILE)) {
+        Ok(entry) => entry,
+        Err(_) => return Ok(None),
+    };
+    let blob = repo.find_blob(entry.id()).map_err(git_error)?;
+
+    // Commits made before the config was split also hold the unlock material, only the settings
+    // kept in the vault config file are taken
+    Ok(config
+        .with_file_content(VAULT_CONFIG_FILE, blob.content())?
+        .file_fields(VAULT_CONFIG_FILE))
+}
+
+/// Three-way merge of the settings of the vault, field by field. Conflicts without resolution
+/// are added to `conflicts`.
+///
+/// The unlock material and the settings of this device aren't committed, they are kept as they
+/// are.
+fn merge_settings(
+    repo: &Repository,
+    vault: &Vault,
+    base: Option<&Tree>,
+    theirs: &Tree,
+    resolutions: &[ConflictResolution],
+    conflicts: &mut Vec<SyncConflict>,
+) -> Result<VaultConfig, PassyError> {
+    let config = VaultConfig::read(&vault.path)?;
+    let failed = || PassyError::FailedToReadVaultConfig(vault.path.join(VAULT_CONFIG_FILE));
+
+    let theirs_fields = match settings_in_tree(repo, &config, theirs)? {
+        Some(fields) => fields,
+        None => return Ok(config),
+    };
+    let base_fields = match base {
+        Some(base) => settings_in_tree(repo, &config, base)?.unwrap_or_default(),
+        None => Map::new(),
+    };
+    let ours_fields = config.file_fields(VAULT_CONFIG_FILE).ok_or_else(failed)?;
+
+    let mut merged = ours_fields.clone();
+    for (field, theirs_value) in theirs_fields {
+        let ours_value = ours_fields.get(&field);
+        let base_value = base_fields.get(&field);
+
+        if ours_value == Some(&theirs_value) || base_value == Some(&theirs_value) {
+            continue;
+        }
+
+        let take_theirs = match ours_value {
+            Some(ours_value) if base_value != Some(ours_value) => {
+                match resolution(resolutions, VAULT_CONFIG_FILE, Some(&field)) {
+                    Some(side) => matches!(side, SyncSide::Theirs),
+                    None => {
+                        conflicts.push(SyncConflict::Setting {
+                            field: field.clone(),
+                            ours: ours_value.clone(),
+                            theirs: theirs_value.clone(),
+                        });
+                        false
+                    }
+                }
+            }
+            _ => true,
+        };
+
+        if take_theirs {
+            merged.insert(field, theirs_value);
+        }
+    }
+
+    let content = serde_json::to_vec(&merged).map_err(|_| failed())?;
+    config.with_file_content(VAULT_CONFIG_FILE, &content)
+}
+
+/// Writes the merged `config`, passwords of the vault are ciphered again if the cipher or the
+/// hidden names setting changed
+fn apply_config(vault: &Vault, config: VaultConfig) -> Result<Vault, PassyError> {
+    if config.cipher != vault.config.cipher || config.hidden_names != vault.config.hidden_names {
+        let appdata = vault
+            .path
+            .parent()
+            .ok_or(PassyError::FailedToReadVaultConfig(vault.path.clone()))?;
+        return vault::reencrypt(appdata, vault, vault.key.clone(), config);
+    }
+
+    config.write(&vault.path)?;
+    Ok(Vault {
+        config,
+        ..vault.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password(fields: &[(&str, &str)]) -> Password {
+        let data: HashMap<&str, &str> = fields.iter().copied().collect();
+        serde_json::from_value(serde_json::json!({ "path": "site", "data": data })).unwrap()
+    }
+
+    fn field<'a>(password: &'a Option<Password>, field: &str) -> Option<&'a str> {
+        password
+            .as_ref()
+            .and_then(|password| password.data().get().get(field))
+            .map(|value| &**value)
+    }
+
+    fn resolve(field: Option<&str>, side: SyncSide) -> ConflictResolution {
+        ConflictResolution {
+            path: "site".to_string(),
+            field: field.map(str::to_string),
+            side,
+        }
+    }
+
+    #[test]
+    fn takes_the_side_that_changed() {
+        let base = password(&[("username", "alice"), ("password", "a")]);
+        let mut conflicts = Vec::new();
+
+        let merged = merge_entry(
+            "site",
+            Some(&base),
+            Some(password(&[("username", "alice"), ("password", "a")])),
+            Some(password(&[("username", "alice"), ("password", "b")])),
+            &[],
+            &mut conflicts,
+        );
+
+        assert!(conflicts.is_empty());
+        assert_eq!(field(&merged, "password"), Some("b"));
+    }
+
+    #[test]
+    fn merges_fields_changed_on_each_side() {
+        let base = password(&[("username", "alice"), ("password", "a"), ("url", "a.com")]);
+        let mut conflicts = Vec::new();
+
+        let merged = merge_entry(
+            "site",
+            Some(&base),
+            Some(password(&[("username", "bob"), ("password", "a")])),
+            Some(password(&[
+                ("username", "alice"),
+                ("password", "b"),
+                ("url", "a.com"),
+                ("note", "new"),
+            ])),
+            &[],
+            &mut conflicts,
+        );
+
+        assert!(conflicts.is_empty());
+        assert_eq!(field(&merged, "username"), Some("bob"));
+        assert_eq!(field(&merged, "password"), Some("b"));
+        assert_eq!(field(&merged, "url"), None);
+        assert_eq!(field(&merged, "note"), Some("new"));
+    }
+
+    #[test]
+    fn field_changed_on_both_sides_needs_a_resolution() {
+        let base = password(&[("password", "a")]);
+        let ours = || Some(password(&[("password", "b")]));
+        let theirs = || Some(password(&[("password", "c")]));
+
+        let mut conflicts = Vec::new();
+        let merged = merge_entry("site", Some(&base), ours(), theirs(), &[], &mut conflicts);
+
+        assert!(matches!(
+            conflicts.as_slice(),
+            [SyncConflict::Field { field, .. }] if field == PASSWORD_FIELD
+        ));
+        assert_eq!(field(&merged, "password"), Some("b"));
+
+        let mut conflicts = Vec::new();
+        let merged = merge_entry(
+            "site",
+            Some(&base),
+            ours(),
+            theirs(),
+            &[resolve(Some(PASSWORD_FIELD), SyncSide::Theirs)],
+            &mut conflicts,
+        );
+
+        assert!(conflicts.is_empty());
+        assert_eq!(field(&merged, "password"), Some("c"));
+    }
+
+    #[test]
+    fn password_change_date_follows_the_password() {
+        let base = password(&[("password", "a")]);
+        let mut theirs = password(&[("password", "b")]);
+        theirs.password_changed = Some(42);
+
+        let merged = merge_entry(
+            "site",
+            Some(&base),
+            Some(password(&[("password", "a"), ("username", "alice")])),
+            Some(theirs),
+            &[],
+            &mut Vec::new(),
+        );
+
+        assert_eq!(merged.and_then(|merged| merged.password_changed), Some(42));
+    }
+
+    #[test]
+    fn deletion_is_kept_if_the_other_side_is_unchanged() {
+        let base = password(&[("password", "a")]);
+        let mut conflicts = Vec::new();
+
+        let merged = merge_entry(
+            "site",
+            Some(&base),
+            Some(password(&[("password", "a")])),
+            None,
+            &[],
+            &mut conflicts,
+        );
+
+        assert!(conflicts.is_empty());
+        assert!(merged.is_none());
+    }
+
+    #[test]
+    fn deletion_of_a_changed_entry_needs_a_resolution() {
+        let base = password(&[("password", "a")]);
+
+        let mut conflicts = Vec::new();
+        let merged = merge_entry(
+            "site",
+            Some(&base),
+            None,
+            Some(password(&[("password", "b")])),
+            &[],
+            &mut conflicts,
+        );
+
+        assert!(matches!(
+            conflicts.as_slice(),
+            [SyncConflict::Entry {
+                deleted_ours: true,
+                ..
+            }]
+        ));
+        assert!(merged.is_none());
+
+        let mut conflicts = Vec::new();
+        let merged = merge_entry(
+            "site",
+            Some(&base),
+            None,
+            Some(password(&[("password", "b")])),
+            &[resolve(None, SyncSide::Theirs)],
+            &mut conflicts,
+        );
+
+        assert!(conflicts.is_empty());
+        assert_eq!(field(&merged, "password"), Some("b"));
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    cmp::Reverse,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic,
+    crypto::{open, seal},
+    error::{PasswordWriteFailedReason, PassyError},
+    names::random_name,
+    password::{unix_now, Password},
+    revisions::{self, history_file},
+    vault::Vault,
+};
+
+/// Folder of the user directory deleted passwords are moved to
+pub const TRASH_DIR: &str = ".trash";
+pub const TRASH_INDEX_FILE: &str = "trash.index";
+
+/// Days deleted passwords are kept, unless configured otherwise
+pub const DEFAULT_TRASH_RETENTION: u64 = 30;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// A deleted password, its file is kept under an opaque name so that the trash tells nothing
+/// about it without the vault key
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrashedPassword {
+    pub id: String,
+    pub path: String,
+    /// Unix time at which the password was deleted
+    pub deleted_at: u64,
+}
+
+/// Ciphered list of the passwords in the trash
+#[derive(Serialize, Deserialize, Default)]
+struct TrashIndex(Vec<TrashedPassword>);
+
+impl TrashIndex {
+    fn read(vault: &Vault) -> Result<Self, PassyError> {
+        let path = trash_path(vault).join(TRASH_INDEX_FILE);
+        if !Path::exists(&path) {
+            return Ok(TrashIndex::default());
+        }
+
+        let content = fs::read(&path).map_err(|_| PassyError::FailedToReadIndex(path.clone()))?;
+        TrashIndex::from_content(vault, &path, &content)
+    }
+
+    /// Deciphers the index from `content`, the content of the index file at `path`
+    fn from_content(vault: &Vault, path: &Path, content: &[u8]) -> Result<Self, PassyError> {
+        let data = open(&vault.key, content)
+            .map_err(|_| PassyError::FailedToReadIndex(path.to_owned()))?;
+        serde_json::from_slice(&data).map_err(|_| PassyError::FailedToReadIndex(path.to_owned()))
+    }
+
+    /// Writes the index, the trash is removed altogether once it is empty
+    fn write(&self, vault: &Vault) -> Result<(), PassyError> {
+        if self.0.is_empty() {
+            return empty(vault);
+        }
+
+        let path = trash_path(vault).join(TRASH_INDEX_FILE);
+        let data =
+            serde_json::to_vec(self).map_err(|_| PassyError::FailedToWriteIndex(path.clone()))?;
+        let content =
+            seal(&vault.key, &data).map_err(|_| PassyError::FailedToWriteIndex(path.clone()))?;
+        atomic::write(&path, content).map_err(|_| PassyError::FailedToWriteIndex(path))
+    }
+}
+
+fn trash_path(vault: &Vault) -> PathBuf {
+    vault.path.join(TRASH_DIR)
+}
+
+fn trashed_file(vault: &Vault, id: &str) -> PathBuf {
+    trash_path(vault).join(trashed_file_name(id))
+}
+
+/// Name of the file of the password `id` in the trash folder
+pub fn trashed_file_name(id: &str) -> String {
+    id.to_string() + ".passy"
+}
+
+/// Removes the files of the password `id` of the trash, its index is left as it is
+fn remove_files(vault: &Vault, id: &str) -> Result<(), PassyError> {
+    let file = trashed_file(vault, id);
+    for file in [history_file(&file), file] {
+        if Path::exists(&file) {
+            fs::remove_file(&file).map_err(|_| PassyError::DeletationFailed(file))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the password at `path` to the trash along with its history, it is still ciphered and
+/// bound to its path
+pub fn move_to_trash(vault: &Vault, path: &str) -> Result<(), PassyError> {
+    let trash = trash_path(vault);
+    fs::create_dir_all(&trash)
+        .map_err(|e| PassyError::FailedToCreateDir((trash.clone(), e.to_string())))?;
+
+    let id = random_name();
+    let target = trashed_file(vault, &id);
+    Password::remove_with(vault, path, |file| {
+        fs::rename(file, &target)?;
+
+        let history = history_file(file);
+        if Path::exists(&history) {
+            fs::rename(history, history_file(&target))?;
+        }
+        Ok(())
+    })?;
+
+    let mut index = TrashIndex::read(vault)?;
+    index.0.push(TrashedPassword {
+        id,
+        path: path.to_string(),
+        deleted_at: unix_now(),
+    });
+    index.write(vault)
+}
+
+/// Passwords in the trash, most recently deleted first
+pub fn list(vault: &Vault) -> Result<Vec<TrashedPassword>, PassyError> {
+    let mut trashed = TrashIndex::read(vault)?.0;
+    trashed.sort_by_key(|trashed| Reverse(trashed.deleted_at));
+    Ok(trashed)
+}
+
+/// Puts the password `id` of the trash back where it was deleted from
+pub fn restore(vault: &Vault, id: &str) -> Result<Password, PassyError> {
+    let mut index = TrashIndex::read(vault)?;
+    let position = index
+        .0
+        .iter()
+        .position(|trashed| trashed.id == id)
+        .ok_or(PassyError::NotInTrash(id.to_string()))?;
+    let path = index.0[position].path.clone();
+
+    if Password::exists(vault, &path)? {
+        return Err(PassyError::PasswordAlreadyExists(path));
+    }
+
+    let file = Password::get_or_create_path(vault, &path)?;
+    if let Some(parent_dir) = file.parent() {
+        fs::create_dir_all(parent_dir)
+            .map_err(|e| PassyError::FailedToCreateDir((parent_dir.to_owned(), e.to_string())))?;
+    }
+    let trashed = trashed_file(vault, id);
+    fs::rename(&trashed, &file).map_err(|_| {
+        PassyError::FailedToWritePassword(file.clone(), PasswordWriteFailedReason::WritePermission)
+    })?;
+
+    let history = history_file(&trashed);
+    if Path::exists(&history) {
+        fs::rename(&history, history_file(&file))
+            .map_err(|_| PassyError::FailedToWriteHistory(history_file(&file)))?;
+    }
+
+    index.0.remove(position);
+    index.write(vault)?;
+
+    let mut password = Password::read(vault, &path)?;
+    password.update_strength();
+    Ok(password)
+}
+
+/// Passwords in `content`, the trash index of another version of the vault, e.g. on another
+/// device. `path` is the index file, for errors.
+pub fn read_index(
+    vault: &Vault,
+    path: &Path,
+    content: &[u8],
+) -> Result<Vec<TrashedPassword>, PassyError> {
+    Ok(TrashIndex::from_content(vault, path, content)?.0)
+}
+
+/// Adds `trashed`, deleted in another version of the vault, to the trash. `content` and
+/// `history` are its files there.
+pub fn add(
+    vault: &Vault,
+    trashed: TrashedPassword,
+    content: &[u8],
+    history: Option<&[u8]>,
+) -> Result<(), PassyError> {
+    let trash = trash_path(vault);
+    fs::create_dir_all(&trash)
+        .map_err(|e| PassyError::FailedToCreateDir((trash.clone(), e.to_string())))?;
+
+    let file = trashed_file(vault, &trashed.id);
+    atomic::write(&file, content).map_err(|_| {
+        PassyError::FailedToWritePassword(file.clone(), PasswordWriteFailedReason::WritePermission)
+    })?;
+    if let Some(history) = history {
+        atomic::write(&history_file(&file), history)
+            .map_err(|_| PassyError::FailedToWriteHistory(history_file(&file)))?;
+    }
+
+    let mut index = TrashIndex::read(vault)?;
+    index.0.push(trashed);
+    index.write(vault)
+}
+
+/// Deletes for good the password `id` of the trash, e.g. once it was restored or purged in
+/// another version of the vault
+pub fn remove(vault: &Vault, id: &str) -> Result<(), PassyError> {
+    let mut index = TrashIndex::read(vault)?;
+    index.0.retain(|trashed| trashed.id != id);
+    remove_files(vault, id)?;
+    index.write(vault)
+}
+
+/// Deletes the password at `path` which is in the trash already as `id`, e.g. as it was deleted
+/// in another version of the vault. Its history joins the one kept in the trash.
+pub fn remove_trashed(vault: &Vault, path: &str, id: &str) -> Result<(), PassyError> {
+    let target = trashed_file(vault, id);
+    if let Some(file) = Password::get_path(vault, path)? {
+        revisions::merge_file(vault, path, &file, &target)?;
+    }
+
+    Password::delete(vault, path.to_string())
+}
+
+/// Deletes for good every password of the trash
+pub fn empty(vault: &Vault) -> Result<(), PassyError> {
+    let trash = trash_path(vault);
+    if Path::exists(&trash) {
+        fs::remove_dir_all(&trash).map_err(|_| PassyError::DeletationFailed(trash))?;
+    }
+
+    Ok(())
+}
+
+/// Deletes for good the passwords kept longer than the retention period, returns whether any was
+pub fn purge_expired(vault: &Vault) -> Result<bool, PassyError> {
+    let retention = match vault.config.trash_retention {
+        Some(days) => days * DAY,
+        None => return Ok(false),
+    };
+
+    let mut index = TrashIndex::read(vault)?;
+    let now = unix_now();
+    let (expired, kept): (Vec<_>, Vec<_>) = index
+        .0
+        .into_iter()
+        .partition(|trashed| now.saturating_sub(trashed.deleted_at) > retention);
+
+    if expired.is_empty() {
+        return Ok(false);
+    }
+
+    for trashed in &expired {
+        remove_files(vault, &trashed.id)?;
+    }
+
+    index.0 = kept;
+    index.write(vault)?;
+
+    Ok(true)
+}
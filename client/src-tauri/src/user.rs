@@ -1,4 +1,8 @@
-use crate::error::PassyError;
+use crate::{
+    error::PassyError,
+    kdf::read_key_file,
+    vault::{VaultConfig, VaultOptions},
+};
 use serde::Serialize;
 use std::{
     fs,
@@ -19,10 +23,22 @@ pub struct AppUser {
 }
 
 impl AppUser {
-    pub fn create(appdata: &Path, name: String) -> Result<AppUser, PassyError> {
+    pub fn create(
+        appdata: &Path,
+        name: String,
+        password: &str,
+        key_file: Option<&Path>,
+        options: VaultOptions,
+    ) -> Result<AppUser, PassyError> {
+        let key_file = key_file.map(read_key_file).transpose()?;
+
         let folder_name = Username::from(&name);
         let path = appdata.join(&folder_name);
         fs::create_dir(path.clone()).map_err(|_| PassyError::UserAlreadyExists(name.clone()))?;
+        let (mut config, _) = VaultConfig::generate(password, key_file.as_ref())?;
+        config.cipher = options.cipher;
+        config.hidden_names = options.hidden_names;
+        config.write(&path)?;
 
         Ok(AppUser {
             name: folder_name,
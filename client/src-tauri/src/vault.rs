@@ -0,0 +1,572 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    atomic,
+    crypto::{generate_key, open, seal, CipherSuite},
+    error::PassyError,
+    kdf::{key_file_fingerprint, read_key_file, KdfParams, KeyFileHash},
+    lock::{LockMode, VaultLock},
+    names::{NamesIndex, NAMES_INDEX_FILE},
+    password::{list_entries, Password},
+    revisions::{self, DEFAULT_HISTORY_DEPTH},
+    secret::SecretKey,
+    session::DEFAULT_AUTO_LOCK,
+    trash::DEFAULT_TRASH_RETENTION,
+    user::Username,
+};
+
+pub const VAULT_CONFIG_FILE: &str = "vault.json";
+
+/// Holds what unlocks the vault, which is left out of the git history so that an old master
+/// password can't unwrap the data key from a past commit. It is synced on its own.
+pub const KEYS_FILE: &str = "keys.json";
+
+/// Holds the settings of this device only, the sync remote may include credentials
+pub const DEVICE_FILE: &str = "device.json";
+
+/// Files of the user directory that are never committed
+pub const LOCAL_CONFIG_FILES: [&str; 2] = [KEYS_FILE, DEVICE_FILE];
+
+/// Fields of the config kept in [`KEYS_FILE`]
+const KEY_FIELDS: [&str; 5] = [
+    "kdf",
+    "wrapped_key",
+    "key_check",
+    "key_file",
+    "keys_changed",
+];
+
+/// Fields of the config kept in [`DEVICE_FILE`]
+const DEVICE_FIELDS: [&str; 2] = ["git", "remote"];
+
+/// Known value ciphered with the password key by vaults that predate wrapped keys
+const KEY_CHECK_VALUE: &[u8] = b"passy key check";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VaultConfig {
+    pub kdf: KdfParams,
+    /// Random vault data key, ciphered with the key derived from the master password
+    #[serde(default)]
+    wrapped_key: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_check: Option<Vec<u8>>,
+    /// Fingerprint of the key file required to unlock the vault, if any
+    #[serde(default)]
+    key_file: Option<Vec<u8>>,
+    /// Unix time the master password or key file was last changed
+    #[serde(default)]
+    keys_changed: Option<u64>,
+    /// Cipher new passwords files are written with, existing files record their own
+    #[serde(default)]
+    pub cipher: CipherSuite,
+    /// Passwords files get opaque names on disk, their paths are kept in a ciphered index
+    #[serde(default)]
+    pub hidden_names: bool,
+    /// Seconds of inactivity after which the vault gets locked, never if `None`
+    #[serde(default = "default_auto_lock")]
+    pub auto_lock: Option<u64>,
+    /// Every change gets committed to a git repository in the user directory
+    #[serde(default)]
+    pub git: bool,
+    /// URL or path of the git repository the history is synced with
+    #[serde(default)]
+    pub remote: Option<String>,
+    /// Days deleted passwords are kept in the trash, forever if `None`
+    #[serde(default = "default_trash_retention")]
+    pub trash_retention: Option<u64>,
+    /// Previous versions kept for each password, none if 0
+    #[serde(default = "default_history_depth")]
+    pub history_depth: usize,
+}
+
+fn default_auto_lock() -> Option<u64> {
+    Some(DEFAULT_AUTO_LOCK)
+}
+
+fn default_trash_retention() -> Option<u64> {
+    Some(DEFAULT_TRASH_RETENTION)
+}
+
+fn default_history_depth() -> usize {
+    DEFAULT_HISTORY_DEPTH
+}
+
+/// Vault settings chosen when creating a user
+#[derive(Deserialize, Default)]
+pub struct VaultOptions {
+    #[serde(default)]
+    pub cipher: CipherSuite,
+    #[serde(default)]
+    pub hidden_names: bool,
+}
+
+/// An unlocked vault, everything needed to read and write its passwords
+///
+/// Clones share the same key, which is wiped from memory once the last of them is dropped.
+#[derive(Clone)]
+pub struct Vault {
+    pub username: Username,
+    pub path: PathBuf,
+    pub key: Arc<SecretKey>,
+    pub config: VaultConfig,
+}
+
+impl VaultConfig {
+    /// Creates a config with a new random data key wrapped by `password`, returns the data key
+    pub fn generate(
+        password: &str,
+        key_file: Option<&KeyFileHash>,
+    ) -> Result<(Self, SecretKey), PassyError> {
+        let key = generate_key();
+
+        let mut config = VaultConfig {
+            kdf: KdfParams::generate(),
+            wrapped_key: None,
+            key_check: None,
+            key_file: None,
+            keys_changed: None,
+            cipher: CipherSuite::default(),
+            hidden_names: false,
+            auto_lock: default_auto_lock(),
+            git: false,
+            remote: None,
+            trash_retention: default_trash_retention(),
+            history_depth: DEFAULT_HISTORY_DEPTH,
+        };
+        config.rewrap(&key, password, key_file)?;
+
+        Ok((config, key))
+    }
+
+    /// Wraps the data `key` with a new key derived from `password`, passwords files are untouched
+    pub fn rewrap(
+        &mut self,
+        key: &[u8; 32],
+        password: &str,
+        key_file: Option<&KeyFileHash>,
+    ) -> Result<(), PassyError> {
+        self.kdf = KdfParams::generate();
+        let password_key = self.kdf.derive_key(password, key_file)?;
+        self.key_file = key_file.map(key_file_fingerprint);
+
+        self.wrapped_key =
+            Some(seal(&password_key, key).map_err(|_| PassyError::KeyDerivationFailed)?);
+        self.key_check = None;
+        self.keys_changed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|time| time.as_secs());
+
+        Ok(())
+    }
+
+    fn check_key_file(&self, key_file: Option<&KeyFileHash>) -> Result<(), PassyError> {
+        match (&self.key_file, key_file) {
+            (Some(_), None) => Err(PassyError::KeyFileRequired),
+            (Some(fingerprint), Some(hash)) if *fingerprint != key_file_fingerprint(hash) => {
+                Err(PassyError::WrongKeyFile)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn unwrap_key(&self, password_key: &[u8; 32]) -> Result<Option<SecretKey>, PassyError> {
+        match &self.wrapped_key {
+            Some(wrapped_key) => {
+                let key =
+                    open(password_key, wrapped_key).map_err(|_| PassyError::WrongMasterPassword)?;
+
+                if key.len() != 32 {
+                    return Err(PassyError::WrongMasterPassword);
+                }
+
+                let mut secret = SecretKey::new([0; 32]);
+                secret.copy_from_slice(&key);
+                Ok(Some(secret))
+            }
+            None => {
+                if let Some(key_check) = &self.key_check {
+                    let value = open(password_key, key_check)
+                        .map_err(|_| PassyError::WrongMasterPassword)?;
+
+                    if value.as_slice() != KEY_CHECK_VALUE {
+                        return Err(PassyError::WrongMasterPassword);
+                    }
+                }
+
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn exists(user_path: &Path) -> bool {
+        Path::exists(&user_path.join(VAULT_CONFIG_FILE))
+    }
+
+    pub fn keys_changed(&self) -> Option<u64> {
+        self.keys_changed
+    }
+
+    /// Reads the config from the files of the user directory.
+    ///
+    /// Vaults written before the config was split keep all of it in [`VAULT_CONFIG_FILE`], the
+    /// other files take precedence once they exist.
+    pub fn read(user_path: &Path) -> Result<Self, PassyError> {
+        let path = user_path.join(VAULT_CONFIG_FILE);
+        let mut fields = read_fields(&path)?;
+
+        for file in LOCAL_CONFIG_FILES {
+            let file_path = user_path.join(file);
+            if Path::exists(&file_path) {
+                fields.retain(|field, _| config_file(field) != file);
+                fields.extend(
+                    read_fields(&file_path)?
+                        .into_iter()
+                        .filter(|(field, _)| config_file(field) == file),
+                );
+            }
+        }
+
+        serde_json::from_value(Value::Object(fields))
+            .map_err(|_| PassyError::FailedToReadVaultConfig(path))
+    }
+
+    /// Writes the config to the files of the user directory, [`VAULT_CONFIG_FILE`] last so that
+    /// older versions of it never override the others
+    pub fn write(&self, user_path: &Path) -> Result<(), PassyError> {
+        for file in [KEYS_FILE, DEVICE_FILE, VAULT_CONFIG_FILE] {
+            let path = user_path.join(file);
+            let content = self
+                .file_content(file)
+                .ok_or(PassyError::FailedToWriteVaultConfig(path.clone()))?;
+            atomic::write(&path, content)
+                .map_err(|_| PassyError::FailedToWriteVaultConfig(path))?;
+        }
+
+        Ok(())
+    }
+
+    /// Fields of this config kept in `file`
+    pub fn file_fields(&self, file: &str) -> Option<Map<String, Value>> {
+        Some(
+            self.fields()?
+                .into_iter()
+                .filter(|(field, _)| config_file(field) == file)
+                .collect(),
+        )
+    }
+
+    /// Content of `file` for this config, only the fields kept in that file are included
+    pub fn file_content(&self, file: &str) -> Option<Vec<u8>> {
+        serde_json::to_vec(&self.file_fields(file)?).ok()
+    }
+
+    /// This config with the fields kept in `file` replaced by those of `content`, fields of other
+    /// files in `content` are ignored
+    pub fn with_file_content(&self, file: &str, content: &[u8]) -> Result<Self, PassyError> {
+        let failed = || PassyError::FailedToReadVaultConfig(PathBuf::from(file));
+
+        let mut fields = self.fields().ok_or_else(failed)?;
+        fields.retain(|field, _| config_file(field) != file);
+
+        let new_fields: Map<String, Value> =
+            serde_json::from_slice(content).map_err(|_| failed())?;
+        fields.extend(
+            new_fields
+                .into_iter()
+                .filter(|(field, _)| config_file(field) == file),
+        );
+
+        serde_json::from_value(Value::Object(fields)).map_err(|_| failed())
+    }
+
+    fn fields(&self) -> Option<Map<String, Value>> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(fields)) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+/// File of the user directory the config `field` is kept in
+fn config_file(field: &str) -> &'static str {
+    if KEY_FIELDS.contains(&field) {
+        KEYS_FILE
+    } else if DEVICE_FIELDS.contains(&field) {
+        DEVICE_FILE
+    } else {
+        VAULT_CONFIG_FILE
+    }
+}
+
+fn read_fields(path: &Path) -> Result<Map<String, Value>, PassyError> {
+    let content =
+        fs::read(path).map_err(|_| PassyError::FailedToReadVaultConfig(path.to_owned()))?;
+    serde_json::from_slice(&content)
+        .map_err(|_| PassyError::FailedToReadVaultConfig(path.to_owned()))
+}
+
+/// Unlocks the vault of `username` with its master password and key file.
+///
+/// Older vaults are upgraded along the way: all-zero key vaults and vaults ciphered directly with
+/// the password key get re-ciphered with a new wrapped data key.
+/// Configs kept in a single file are split so that their unlock material stays out of commits.
+pub fn unlock(
+    appdata: &Path,
+    username: &str,
+    password: &str,
+    key_file: Option<&Path>,
+) -> Result<Vault, PassyError> {
+    let user_path = appdata.join(username);
+    let key_file = key_file.map(read_key_file).transpose()?;
+
+    let (new_config, key) = if VaultConfig::exists(&user_path) {
+        let config = VaultConfig::read(&user_path)?;
+        config.check_key_file(key_file.as_ref())?;
+
+        let key_file = config.key_file.as_ref().and(key_file.as_ref());
+        let password_key = config.kdf.derive_key(password, key_file)?;
+
+        if let Some(key) = config.unwrap_key(&password_key)? {
+            if LOCAL_CONFIG_FILES
+                .iter()
+                .any(|file| !Path::exists(&user_path.join(file)))
+            {
+                config.write(&user_path)?;
+            }
+
+            return Ok(Vault {
+                username: username.to_string(),
+                path: user_path,
+                key: Arc::new(key),
+                config,
+            });
+        }
+
+        (VaultConfig::generate(password, key_file)?, password_key)
+    } else {
+        (
+            VaultConfig::generate(password, key_file.as_ref())?,
+            SecretKey::new([0; 32]),
+        )
+    };
+
+    let (config, new_key) = new_config;
+    let old_vault = Vault {
+        username: username.to_string(),
+        path: user_path,
+        key: Arc::new(key),
+        config: config.clone(),
+    };
+
+    reencrypt(appdata, &old_vault, Arc::new(new_key), config)
+}
+
+fn staging_path(appdata: &Path, username: &str) -> PathBuf {
+    appdata.join(format!(".{}.staging", username))
+}
+
+fn backup_path(appdata: &Path, username: &str) -> PathBuf {
+    appdata.join(format!(".{}.old", username))
+}
+
+/// Re-ciphers every password of `vault` with `key` and the settings of `config`, which replaces
+/// the vault config. Returns the vault as it is after that.
+///
+/// Everything is written to a staging directory first, which is then swapped with the user
+/// directory, so the old vault stays intact if anything fails before the swap.
+pub fn reencrypt(
+    appdata: &Path,
+    vault: &Vault,
+    key: Arc<SecretKey>,
+    config: VaultConfig,
+) -> Result<Vault, PassyError> {
+    let username = vault.username.as_str();
+    let staging = staging_path(appdata, username);
+    let backup = backup_path(appdata, username);
+
+    let staging_vault = Vault {
+        username: username.to_string(),
+        path: staging.clone(),
+        key,
+        config,
+    };
+
+    let res: Result<(), PassyError> = (|| {
+        if Path::exists(&staging) {
+            fs::remove_dir_all(&staging)
+                .map_err(|_| PassyError::DeletationFailed(staging.clone()))?;
+        }
+
+        let entries = list_entries(vault)?;
+
+        copy_dir_without_passwords(&vault.path, &staging)?;
+
+        // The names index is built here and written once, rather than by each password
+        let mut index = NamesIndex::default();
+        for (path, file) in entries {
+            let pwd = Password::read_file(vault, &file, path)?;
+            let target = if staging_vault.config.hidden_names {
+                staging.join(index.get_or_insert(&pwd.path) + ".passy")
+            } else {
+                staging.join(pwd.path.clone() + ".passy")
+            };
+
+            pwd.write_to(&staging_vault, &target)?;
+            revisions::copy_file(vault, &staging_vault, &pwd.path, &file, &target)?;
+        }
+
+        if staging_vault.config.hidden_names {
+            index.write(&staging_vault.key, &staging)?;
+        }
+
+        staging_vault.config.write(&staging)
+    })();
+
+    if let Err(e) = res {
+        fs::remove_dir_all(&staging).ok();
+        return Err(e);
+    }
+
+    if fs::rename(&vault.path, &backup).is_err() {
+        fs::remove_dir_all(&staging).ok();
+        return Err(PassyError::VaultSwapFailed(username.to_string()));
+    }
+
+    if fs::rename(&staging, &vault.path).is_err() {
+        fs::rename(&backup, &vault.path).ok();
+        fs::remove_dir_all(&staging).ok();
+        return Err(PassyError::VaultSwapFailed(username.to_string()));
+    }
+
+    fs::remove_dir_all(&backup).ok();
+
+    Ok(Vault {
+        path: vault.path.clone(),
+        ..staging_vault
+    })
+}
+
+/// Copies everything but the files ciphered with the vault key: passwords, their histories and
+/// names index
+///
+/// Hidden directories, such as the git repository of the history, are copied as they are.
+fn copy_dir_without_passwords(from: &Path, to: &Path) -> Result<(), PassyError> {
+    fs::create_dir_all(to)
+        .map_err(|e| PassyError::FailedToCreateDir((to.to_owned(), e.to_string())))?;
+
+    for res in fs::read_dir(from).map_err(|_| PassyError::FailedToReadDir(from.to_owned()))? {
+        let entry = res.map_err(|_| PassyError::FailedToReadDir(from.to_owned()))?;
+        let target = to.join(entry.file_name());
+
+        if entry.path().is_dir() && entry.file_name().to_string_lossy().starts_with('.') {
+            copy_dir(&entry.path(), &target)?;
+        } else if entry.path().is_dir() {
+            copy_dir_without_passwords(&entry.path(), &target)?;
+
+            // Folders left empty would reveal passwords paths of vaults with hidden names
+            if fs::read_dir(&target).is_ok_and(|mut dir| dir.next().is_none()) {
+                fs::remove_dir(&target).map_err(|_| PassyError::DeletationFailed(target))?;
+            }
+        } else if !entry.file_name().to_string_lossy().ends_with(".passy")
+            && !entry.file_name().to_string_lossy().ends_with(".history")
+            && entry.file_name() != NAMES_INDEX_FILE
+        {
+            fs::copy(entry.path(), &target)
+                .map_err(|_| PassyError::FailedToCopyFile(entry.path()))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), PassyError> {
+    fs::create_dir_all(to)
+        .map_err(|e| PassyError::FailedToCreateDir((to.to_owned(), e.to_string())))?;
+
+    for res in fs::read_dir(from).map_err(|_| PassyError::FailedToReadDir(from.to_owned()))? {
+        let entry = res.map_err(|_| PassyError::FailedToReadDir(from.to_owned()))?;
+        let target = to.join(entry.file_name());
+
+        if entry.path().is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .map_err(|_| PassyError::FailedToCopyFile(entry.path()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Finishes or rolls back vault swaps that were interrupted, e.g. by a crash
+///
+/// Vaults locked by another instance of the app may be in the middle of a swap, they are left
+/// as they are.
+pub fn recover_interrupted_swaps(appdata: &Path) -> Result<(), PassyError> {
+    let entries =
+        fs::read_dir(appdata).map_err(|_| PassyError::FailedToReadDir(appdata.to_owned()))?;
+    let mut result = Ok(());
+
+    // A swap that can't be recovered must not keep the others from being recovered
+    for res in entries {
+        let entry = match res {
+            Ok(entry) => entry,
+            Err(_) => {
+                result = Err(PassyError::FailedToReadDir(appdata.to_owned()));
+                continue;
+            }
+        };
+        if let Err(e) = recover_swap(appdata, &entry) {
+            result = Err(e);
+        }
+    }
+
+    result
+}
+
+fn recover_swap(appdata: &Path, entry: &fs::DirEntry) -> Result<(), PassyError> {
+    let name = entry.file_name().to_string_lossy().to_string();
+
+    if !name.starts_with('.') {
+        return Ok(());
+    }
+
+    let username = match name[1..]
+        .strip_suffix(".old")
+        .or(name[1..].strip_suffix(".staging"))
+    {
+        Some(username) => username,
+        None => return Ok(()),
+    };
+    let _lock = match VaultLock::try_acquire(appdata, username, LockMode::Exclusive)? {
+        Some(lock) => lock,
+        None => return Ok(()),
+    };
+
+    if let Some(username) = name[1..].strip_suffix(".old") {
+        let username = Username::from(username);
+        let user_path = appdata.join(&username);
+
+        if Path::exists(&user_path) {
+            // The new vault was swapped in, only the cleanup is missing
+            fs::remove_dir_all(entry.path())
+                .map_err(|_| PassyError::DeletationFailed(entry.path()))?;
+        } else {
+            fs::rename(entry.path(), &user_path)
+                .map_err(|_| PassyError::VaultSwapFailed(username))?;
+        }
+    } else if name.ends_with(".staging") {
+        fs::remove_dir_all(entry.path()).map_err(|_| PassyError::DeletationFailed(entry.path()))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, DebouncedEvent, Debouncer,
+};
+use tauri::Window;
+
+use crate::{
+    error::PassyError,
+    index::PasswordSummary,
+    names::NAMES_INDEX_FILE,
+    password::{list_entries, Password},
+    vault::{Vault, VaultConfig},
+};
+
+/// Time without any change to the user directory after which changes are sent to the UI, so that
+/// a sync client writing many files at once results in a single update
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches the directory of a vault for changes made by other programs, such as a sync client,
+/// and sends the passwords they touched to the UI.
+///
+/// Emits `password_added` and `password_changed` with the summaries of the passwords, and
+/// `password_removed` with the paths of the passwords that are gone. Changes made by the app
+/// itself are sent as well, so the UI should replace the passwords it already has. Stops when
+/// dropped.
+pub struct VaultWatcher {
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+impl VaultWatcher {
+    pub fn start(vault: &Vault, window: Window) -> Result<Self, PassyError> {
+        let failed = || PassyError::FailedToWatchVault(vault.path.clone());
+
+        let mut state = WatchState {
+            vault: vault.clone(),
+            known: list_entries(vault)?
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect(),
+        };
+
+        let mut debouncer = new_debouncer(DEBOUNCE, move |res: DebounceEventResult| {
+            // Errors of the watcher itself can't be reported anywhere, the UI still gets every
+            // password the next time it reads the vault
+            if let Ok(events) = res {
+                state.handle(&events, &window);
+            }
+        })
+        .map_err(|_| failed())?;
+
+        debouncer
+            .watcher()
+            .watch(&vault.path, RecursiveMode::Recursive)
+            .map_err(|_| failed())?;
+
+        Ok(VaultWatcher {
+            _debouncer: debouncer,
+        })
+    }
+}
+
+struct WatchState {
+    vault: Vault,
+    /// Paths of the passwords the UI was last told about
+    known: HashSet<String>,
+}
+
+impl WatchState {
+    fn handle(&mut self, events: &[DebouncedEvent], window: &Window) {
+        let changed: HashSet<&Path> = events
+            .iter()
+            .map(|event| event.path.as_path())
+            .filter(|file| self.is_vault_file(file))
+            .collect();
+
+        if changed.is_empty() {
+            return;
+        }
+
+        // Settings such as hidden names may have been changed by the other program, the key stays
+        // the same as long as the vault isn't locked
+        if let Ok(config) = VaultConfig::read(&self.vault.path) {
+            self.vault.config = config;
+        }
+
+        let entries: HashMap<PathBuf, String> = match list_entries(&self.vault) {
+            Ok(entries) => entries
+                .into_iter()
+                .map(|(path, file)| (file, path))
+                .collect(),
+            Err(_) => return,
+        };
+
+        let mut added = Vec::new();
+        let mut updated = Vec::new();
+
+        for file in changed {
+            let path = match entries.get(file) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            // Only the files that changed are deciphered, those that can't be yet are picked up
+            // by their next event
+            let content = match fs::read(file) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            let password = match Password::from_content(&self.vault, file, &content, path.clone()) {
+                Ok(password) => password,
+                Err(_) => continue,
+            };
+
+            if self.known.contains(path) {
+                updated.push(PasswordSummary::from(&password));
+            } else {
+                added.push(PasswordSummary::from(&password));
+            }
+        }
+
+        let current: HashSet<String> = entries.into_values().collect();
+        let removed: Vec<String> = self.known.difference(&current).cloned().collect();
+        self.known = current;
+
+        if !added.is_empty() {
+            let _ = window.emit("password_added", added);
+        }
+        if !updated.is_empty() {
+            let _ = window.emit("password_changed", updated);
+        }
+        if !removed.is_empty() {
+            let _ = window.emit("password_removed", removed);
+        }
+    }
+
+    /// Whether `file` can hold passwords or tell where they are, which leaves out hidden folders
+    /// such as the trash or the git repository
+    fn is_vault_file(&self, file: &Path) -> bool {
+        let relative = match file.strip_prefix(&self.vault.path) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+
+        let hidden = relative.parent().is_some_and(|parent| {
+            parent.components().any(|component| match component {
+                Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+                _ => false,
+            })
+        });
+
+        !hidden
+            && (file
+                .extension()
+                .is_some_and(|extension| extension == "passy")
+                || file
+                    .file_name()
+                    .is_some_and(|name| name == NAMES_INDEX_FILE))
+    }
+}
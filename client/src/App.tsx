@@ -1,6 +1,6 @@
 import { useEffect, useRef, useState } from "react";
 import { invoke } from "@tauri-apps/api/tauri";
-import { AccountData, PluginCommandResPayload, User } from "./types";
+import { AccountData, Password, PasswordSummary, PluginCommandResPayload, User } from "./types";
 import { LoginDialog } from "./LoginDialog";
 import { PasswordEdit } from "./PasswordEdit";
 import { Sidebar } from "./Sidebar";
@@ -20,6 +20,7 @@ function App() {
     const [selectedUser, setSelectedUser] = useState(0)
     const [data, setData] = useState<AccountData | null>(null)
     const [openedPassword, setOpenedPassword] = useState<string | null>(null)
+    const [password, setPassword] = useState<Password | null>(null)
 
     const dialog = useRef<HTMLDialogElement>(null);
     const searchInput = useRef<HTMLInputElement>(null)
@@ -49,6 +50,55 @@ function App() {
             })
     }, [])
 
+    // The list only holds summaries, the opened password is deciphered on its own
+    useEffect(() => {
+        if (openedPassword == null) {
+            setPassword(null)
+            return
+        }
+
+        invoke("get_password", { path: openedPassword })
+            .then((p) => setPassword(p as Password))
+            .catch((err) => console.error(err))
+
+        // Changes not made by the user don't postpone the auto lock
+        const unlisten = listen<PasswordSummary[]>("password_changed", e => {
+            if (!e.payload.some(p => p.path == openedPassword)) return
+
+            invoke("get_password", { path: openedPassword, refresh: true })
+                .then((p) => setPassword(p as Password))
+                .catch((err) => console.error(err))
+        })
+
+        return () => { unlisten.then(f => f()) }
+    }, [openedPassword])
+
+    // Passwords changed by other programs, such as a sync client, are sent by the backend
+    useEffect(() => {
+        function patchPasswords(patch: (passwords: PasswordSummary[]) => PasswordSummary[]) {
+            setData(d => d && { ...d, passwords: patch(d.passwords) })
+        }
+
+        function replacePasswords(summaries: PasswordSummary[]) {
+            const paths = summaries.map(s => s.path)
+            patchPasswords(passwords => passwords
+                .filter(p => !paths.includes(p.path))
+                .concat(summaries)
+                .sort((a, b) => a.path < b.path ? -1 : 1))
+        }
+
+        const unlisteners = [
+            listen<PasswordSummary[]>("password_added", e => replacePasswords(e.payload)),
+            listen<PasswordSummary[]>("password_changed", e => replacePasswords(e.payload)),
+            listen<string[]>("password_removed", e => {
+                patchPasswords(passwords => passwords.filter(p => !e.payload.includes(p.path)))
+                setOpenedPassword(opened => opened && e.payload.includes(opened) ? null : opened)
+            }),
+        ]
+
+        return () => unlisteners.forEach(unlisten => unlisten.then(f => f()))
+    }, [])
+
     function fetchUserData() {
         invoke("get_user_data")
             .then((data) => {
@@ -154,8 +204,8 @@ function App() {
 
             {/* Main */}
             <div className="flex-1 bg-gradient-to-bl bg-black from-oxford-blue-200 to-oxford-blue-500 text-oxford-blue-900">
-                {openedPassword != null && data?.passwords ?
-                    <PasswordEdit password={data.passwords.find((p) => p.path == openedPassword)} updateData={updateData}></PasswordEdit>
+                {password ?
+                    <PasswordEdit password={password} updateData={updateData}></PasswordEdit>
                     : ""}
             </div>
         </div>
@@ -2,12 +2,12 @@ import { LegacyRef, MouseEvent, useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/tauri";
 import { FontAwesomeIcon } from "@fortawesome/react-fontawesome";
 import { faFileCirclePlus, faMagnifyingGlass, faRotateRight, faTrash } from "@fortawesome/free-solid-svg-icons";
-import { Password, Setter } from "./types";
+import { PasswordSummary, Setter } from "./types";
 
 
 export interface Props {
     fetchUserData: () => void;
-    passwords: Password[];
+    passwords: PasswordSummary[];
     setOpenedPassword: Setter<string | null>;
     handleFileCreation: () => void;
     addingPassword: LegacyRef<HTMLDivElement>;
@@ -36,12 +36,12 @@ export function Sidebar({ fetchUserData, passwords, setOpenedPassword, addingPas
         fetchUserData();
     }
 
-    function searchFilter(password: Password) {
+    function searchFilter(password: PasswordSummary) {
         let input = searchInput.current?.value.toLowerCase() ?? "";
         if (!input) return true;
 
         return password.path.toLowerCase().includes(input)
-            || Object.keys(password.data).some((v) => v.includes(input))
+            || password.fields.some((v) => v.includes(input))
             || Object.values(password.data).some((v) => v.includes(input));
     }
 
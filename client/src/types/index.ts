@@ -9,10 +9,31 @@ export interface Password {
     data: Record<string, string>
 }
 
+/** Estimated strength of a password, `score` goes from 0, too guessable, to 4, very unguessable */
+export interface Strength {
+    score: number
+    guesses_log10: number
+    crack_time_seconds: number
+    crack_time_display: string
+    feedback: {
+        warning: string | null
+        suggestions: string[]
+    }
+}
+
+/** Entry of the passwords list, with only the fields that tell what it is for */
+export interface PasswordSummary {
+    path: string
+    data: Record<string, string>
+    fields: string[]
+    password_changed: number | null
+    strength: Strength | null
+}
+
 export interface AccountData {
     plugins: Record<string, PluginManifest>
     appdata_path: string
-    passwords: Password[]
+    passwords: PasswordSummary[]
 }
 
 export interface FileTree<T> {